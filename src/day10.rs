@@ -1,18 +1,37 @@
+use std::env;
 use std::fs::File;
+use std::io;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::io::Write;
+use std::num::NonZeroUsize;
 use std::str::FromStr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
 
 use anyhow::anyhow;
 use anyhow::Error;
 use anyhow::Result;
 
+/// Tolerance for rounding in the linear programming relaxation
+const EPSILON: f64 = 1e-9;
+
+/// Most free buttons whose combinations are enumerated for the lights, about a billion in all
+const FREE_LIGHTS: usize = 30;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Light {
     Off,
     On,
 }
 
+/// Bitset over the buttons, lights or joltages of a machine
+#[derive(Clone, Debug)]
+struct Bits {
+    words: Vec<u64>,
+}
+
 #[derive(Debug)]
 struct Machine {
     diagram: Vec<Light>,
@@ -20,6 +39,56 @@ struct Machine {
     joltages: Vec<usize>,
 }
 
+/// Finds how many times to press each button for the fewest total presses that meet every
+/// joltage requirement. Shared by the threads solving machines in parallel
+trait Backend: Sync {
+    fn configure_joltages(&self, machine: &Machine) -> Result<Vec<usize>>;
+}
+
+/// Row reduces the joltage equations over the integers, then branches on the presses of the free
+/// buttons, bounding each branch by the linear programming relaxation
+struct Elimination;
+
+/// Hands the integer program to the Z3 optimizer
+#[cfg(feature = "z3")]
+struct Z3;
+
+/// Joltage equations in reduced row echelon form, where each pivot button's presses follow from
+/// the free buttons in its row
+struct Reduced {
+    matrix: Vec<Vec<i64>>,
+    pivots: Vec<usize>,
+    free: Vec<usize>,
+    resolved: Vec<Vec<usize>>,
+    remaining: Vec<Vec<usize>>,
+    buttons: Vec<Vec<usize>>,
+    joltages: Vec<i64>,
+}
+
+/// A machine being operated by hand, tracking the lights and joltage counters reached so far
+struct Simulator<'a> {
+    machine: &'a Machine,
+    lights: Vec<Light>,
+    counters: Vec<usize>,
+    presses: Vec<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+enum BackendKind {
+    #[cfg_attr(not(feature = "z3"), default)]
+    Elimination,
+    #[cfg_attr(feature = "z3", default)]
+    Z3,
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    backend: BackendKind,
+    plans: bool,
+    jobs: Option<NonZeroUsize>,
+    simulate: Option<usize>,
+}
+
 impl FromStr for Machine {
     type Err = Error;
 
@@ -31,6 +100,7 @@ impl FromStr for Machine {
             .ok_or_else(|| anyhow!("missing light diagram"))?;
         let diagram = diagram
             .get(1..diagram.len() - 1)
+            .filter(|diagram| !diagram.is_empty())
             .ok_or_else(|| anyhow!("light diagram is empty"))?
             .chars()
             .map(Light::new)
@@ -41,6 +111,7 @@ impl FromStr for Machine {
             let schematic = machine.next().unwrap();
             let schematic = schematic
                 .get(1..schematic.len() - 1)
+                .filter(|schematic| !schematic.is_empty())
                 .ok_or_else(|| anyhow!("button schematic is empty"))?
                 .split(',')
                 .map(str::parse)
@@ -54,11 +125,39 @@ impl FromStr for Machine {
             .ok_or_else(|| anyhow!("missing joltage requirements"))?;
         let joltages = joltages
             .get(1..joltages.len() - 1)
+            .filter(|joltages| !joltages.is_empty())
             .ok_or_else(|| anyhow!("joltage requirements are empty"))?
             .split(',')
             .map(str::parse)
             .collect::<Result<Vec<_>, _>>()?;
 
+        if diagram.len() != joltages.len() {
+            return Err(anyhow!(
+                "light diagram has {} lights but there are {} joltage requirements",
+                diagram.len(),
+                joltages.len(),
+            ));
+        }
+
+        for (b, button) in buttons.iter().enumerate() {
+            if let Some(i) = button.iter().find(|&&i| i >= diagram.len()) {
+                return Err(anyhow!(
+                    "button {b} is wired to light {i}, but there are only {} lights",
+                    diagram.len(),
+                ));
+            }
+
+            if let Some(i) = (1..button.len()).find(|&k| button[..k].contains(&button[k])) {
+                return Err(anyhow!("button {b} is wired to light {} twice", button[i]));
+            }
+
+            if let Some(b_first) = buttons[..b].iter().position(|first| {
+                first.len() == button.len() && first.iter().all(|i| button.contains(i))
+            }) {
+                return Err(anyhow!("button {b} duplicates button {b_first}"));
+            }
+        }
+
         Ok(Self {
             diagram,
             buttons,
@@ -82,54 +181,257 @@ impl Light {
             Self::On => Self::Off,
         }
     }
+
+    const fn symbol(self) -> char {
+        match self {
+            Self::Off => '.',
+            Self::On => '#',
+        }
+    }
+}
+
+impl Bits {
+    fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+        }
+    }
+
+    fn get(&self, i: usize) -> bool {
+        self.words[i / 64] >> (i % 64) & 1 == 1
+    }
+
+    fn toggle(&mut self, i: usize) {
+        self.words[i / 64] ^= 1 << (i % 64);
+    }
+
+    fn xor(&mut self, other: &Self) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word ^= other;
+        }
+    }
+
+    fn count_ones(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    fn union(&mut self, other: &Self) {
+        for (word, other) in self.words.iter_mut().zip(&other.words) {
+            *word |= other;
+        }
+    }
+
+    fn ones(&self) -> Vec<usize> {
+        (0..self.words.len() * 64)
+            .filter(|&i| self.get(i))
+            .collect()
+    }
 }
 
 impl Machine {
-    fn configure_lights(&self) -> usize {
-        fn dfs(
-            diagram: &[Light],
-            buttons: &[Vec<usize>],
-            start: usize,
-            presses: usize,
-            presses_min: usize,
-            state: &mut [Light],
-        ) -> usize {
-            if state == diagram {
-                return presses;
-            }
-
-            if presses >= presses_min {
-                return presses;
-            }
-
-            let mut presses_min = presses_min;
-            for i in start..buttons.len() {
-                for &b in &buttons[i] {
-                    state[b] = state[b].toggle();
+    /// Solves the lights as a linear system over GF(2), with a row per light marking the buttons
+    /// that toggle it, then walks the null space for the fewest presses. Returns how many times
+    /// to press each button
+    fn configure_lights(&self) -> Result<Vec<usize>> {
+        let cols = self.buttons.len();
+
+        // Each row also tracks which lights were added into it, to explain a contradiction
+        let mut rows = self
+            .diagram
+            .iter()
+            .enumerate()
+            .map(|(i, &light)| {
+                let mut lights = Bits::new(self.diagram.len());
+                lights.toggle(i);
+
+                (Bits::new(cols), light == Light::On, lights)
+            })
+            .collect::<Vec<_>>();
+        for (b, button) in self.buttons.iter().enumerate() {
+            for &i in button {
+                rows[i].0.toggle(b);
+            }
+        }
+
+        let mut pivots = Vec::new();
+        for col in 0..cols {
+            let r = pivots.len();
+            let Some(p) = (r..rows.len()).find(|&i| rows[i].0.get(col)) else {
+                continue;
+            };
+
+            rows.swap(r, p);
+            let (pivot, rhs, lights) = rows[r].clone();
+
+            for (i, row) in rows.iter_mut().enumerate() {
+                if i != r && row.0.get(col) {
+                    row.0.xor(&pivot);
+                    row.1 ^= rhs;
+                    row.2.xor(&lights);
                 }
+            }
 
-                presses_min = presses_min.min(dfs(
-                    diagram,
-                    buttons,
-                    i + 1,
-                    presses + 1,
-                    presses_min,
-                    state,
-                ));
+            pivots.push(col);
+        }
 
-                for &b in &buttons[i] {
-                    state[b] = state[b].toggle();
+        // A row left without buttons sums lights that every button toggles an even number of
+        // times, so an odd number of them on can never be reached
+        if let Some((_, _, lights)) = rows[pivots.len()..].iter().find(|(_, rhs, _)| *rhs) {
+            return Err(match lights.ones().as_slice() {
+                [i] => anyhow!("light {i} must be on but no button toggles it"),
+                lights => anyhow!(
+                    "lights {lights:?} have an odd number on, but every button toggles an even \
+                     number of them",
+                ),
+            });
+        }
+
+        // Leaving every free button unpressed determines each pivot button from its row alone
+        let mut presses = Bits::new(cols);
+        for (&col, (_, rhs, _)) in pivots.iter().zip(&rows) {
+            if *rhs {
+                presses.toggle(col);
+            }
+        }
+
+        // Pressing a free button also flips every pivot button whose row contains it
+        let basis = (0..cols)
+            .filter(|col| !pivots.contains(col))
+            .map(|free| {
+                let mut basis = Bits::new(cols);
+                basis.toggle(free);
+
+                for (&col, (row, _, _)) in pivots.iter().zip(&rows) {
+                    if row.get(free) {
+                        basis.toggle(col);
+                    }
                 }
+
+                basis
+            })
+            .collect::<Vec<_>>();
+
+        if basis.len() > FREE_LIGHTS {
+            return Err(anyhow!(
+                "{} buttons are free after elimination, at most {FREE_LIGHTS} can be enumerated",
+                basis.len(),
+            ));
+        }
+
+        // Gray code order changes a single free button between consecutive combinations
+        let mut presses_min = presses.clone();
+        for combination in 1..1_u64 << basis.len() {
+            presses.xor(&basis[combination.trailing_zeros() as usize]);
+
+            if presses.count_ones() < presses_min.count_ones() {
+                presses_min = presses.clone();
             }
+        }
 
-            presses_min
+        Ok((0..cols).map(|b| usize::from(presses_min.get(b))).collect())
+    }
+
+    /// Returns how many times to press each button
+    fn configure_joltages(&self, backend: &dyn Backend) -> Result<Vec<usize>> {
+        for (i, &joltage) in self.joltages.iter().enumerate() {
+            if joltage > 0 && !self.buttons.iter().any(|button| button.contains(&i)) {
+                return Err(anyhow!(
+                    "joltage {i} must reach {joltage} but no button raises it",
+                ));
+            }
         }
 
+        backend.configure_joltages(self)
+    }
+
+    /// Replays `plan` from every light off, checking it ends on the diagram
+    fn verify_lights(&self, plan: &[usize]) -> Result<()> {
+        self.verify_len(plan)?;
+
         let mut state = vec![Light::Off; self.diagram.len()];
-        dfs(&self.diagram, &self.buttons, 0, 0, usize::MAX, &mut state)
+        for (button, &presses) in self.buttons.iter().zip(plan) {
+            for _ in 0..presses % 2 {
+                for &i in button {
+                    state[i] = state[i].toggle();
+                }
+            }
+        }
+
+        for (i, (light, target)) in state.iter().zip(&self.diagram).enumerate() {
+            if light != target {
+                return Err(anyhow!(
+                    "plan leaves light {i} {light:?} instead of {target:?}",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replays `plan` from every counter at zero, checking it ends on the joltage requirements
+    fn verify_joltages(&self, plan: &[usize]) -> Result<()> {
+        self.verify_len(plan)?;
+
+        let mut counters = vec![0; self.joltages.len()];
+        for (button, &presses) in self.buttons.iter().zip(plan) {
+            for &i in button {
+                counters[i] += presses;
+            }
+        }
+
+        for (i, (counter, target)) in counters.iter().zip(&self.joltages).enumerate() {
+            if counter != target {
+                return Err(anyhow!(
+                    "plan leaves joltage {i} at {counter} instead of {target}",
+                ));
+            }
+        }
+
+        Ok(())
     }
 
-    fn configure_joltages(&self) -> Result<usize> {
+    fn verify_len(&self, plan: &[usize]) -> Result<()> {
+        if plan.len() == self.buttons.len() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "plan has {} buttons but machine has {}",
+                plan.len(),
+                self.buttons.len(),
+            ))
+        }
+    }
+}
+
+impl Backend for Elimination {
+    fn configure_joltages(&self, machine: &Machine) -> Result<Vec<usize>> {
+        let reduced = Reduced::new(machine)?;
+
+        let mut plan = vec![0; machine.buttons.len()];
+        let mut plan_min = None;
+        reduced.search(0, &mut plan, &reduced.joltages, 0, &mut plan_min);
+
+        let (_, plan) = plan_min.ok_or_else(|| {
+            anyhow!(
+                "joltages can only be met with a negative or fractional number of presses of \
+                 buttons {:?}",
+                reduced.pivots,
+            )
+        })?;
+
+        Ok(plan
+            .into_iter()
+            .map(usize::try_from)
+            .collect::<Result<_, _>>()?)
+    }
+}
+
+#[cfg(feature = "z3")]
+impl Backend for Z3 {
+    fn configure_joltages(&self, machine: &Machine) -> Result<Vec<usize>> {
         use z3::ast::Int;
         use z3::Optimize;
         use z3::SatResult;
@@ -139,7 +441,7 @@ impl Machine {
 
         // Creates integer variables for each button
         // How many times to press
-        let x = (0..self.buttons.len())
+        let x = (0..machine.buttons.len())
             .map(|i| Int::new_const(format!("x{i}")))
             .collect::<Vec<Int>>();
 
@@ -150,11 +452,11 @@ impl Machine {
         }
 
         // Constraint 2: sum of contributing buttons equals target joltage
-        for i in 0..self.joltages.len() {
-            let target = Int::from_i64(i64::try_from(self.joltages[i])?);
+        for i in 0..machine.joltages.len() {
+            let target = Int::from_i64(i64::try_from(machine.joltages[i])?);
 
             // Finds all buttons that affect this joltage
-            let contributing = self
+            let contributing = machine
                 .buttons
                 .iter()
                 .enumerate()
@@ -163,8 +465,11 @@ impl Machine {
 
             if contributing.is_empty() {
                 // No buttons affect this joltage, target must be zero
-                if self.joltages[i] != 0 {
-                    return Err(anyhow!("unsolvable"));
+                if machine.joltages[i] != 0 {
+                    return Err(anyhow!(
+                        "joltage {i} must reach {} but no button raises it",
+                        machine.joltages[i],
+                    ));
                 }
             } else {
                 // Sum of all contributing button presses must equal target
@@ -191,47 +496,728 @@ impl Machine {
                     .ok_or_else(|| anyhow!("value is not i64"))?
                     .into_iter()
                     .map(usize::try_from)
-                    .collect::<Result<Vec<_>, _>>()?
-                    .into_iter()
-                    .sum();
+                    .collect::<Result<Vec<_>, _>>()?;
 
                 Ok(presses)
             }
-            SatResult::Unsat => Err(anyhow!("no solution exists for machine")),
-            SatResult::Unknown => Err(anyhow!("solver returned unknown")),
+            SatResult::Unsat => Err(anyhow!(
+                "joltages cannot be met with a non-negative number of presses of each button",
+            )),
+            SatResult::Unknown => Err(anyhow!(
+                "solver returned unknown: {}",
+                opt.get_reason_unknown().unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+impl Reduced {
+    fn new(machine: &Machine) -> Result<Self> {
+        let (rows, cols) = (machine.joltages.len(), machine.buttons.len());
+
+        // Augmented matrix with a row per joltage and a column per button
+        let mut matrix = vec![vec![0; cols + 1]; rows];
+        for (b, button) in machine.buttons.iter().enumerate() {
+            for &i in button {
+                matrix[i][b] = 1;
+            }
+        }
+
+        for (row, &joltage) in matrix.iter_mut().zip(&machine.joltages) {
+            row[cols] = i64::try_from(joltage)?;
+        }
+
+        // Tracks which joltages each row combines, to explain an inconsistency
+        let mut origins = (0..rows)
+            .map(|i| {
+                let mut origin = Bits::new(rows);
+                origin.toggle(i);
+
+                origin
+            })
+            .collect::<Vec<_>>();
+
+        // Scales rows instead of dividing them so every entry stays an integer
+        let mut pivots = Vec::new();
+        for col in 0..cols {
+            let r = pivots.len();
+            let Some(p) = (r..rows).find(|&i| matrix[i][col] != 0) else {
+                continue;
+            };
+
+            matrix.swap(r, p);
+            origins.swap(r, p);
+            let pivot = matrix[r].clone();
+            let origin = origins[r].clone();
+
+            for (i, (row, row_origin)) in matrix.iter_mut().zip(&mut origins).enumerate() {
+                if i != r && row[col] != 0 {
+                    let scale = row[col];
+
+                    for (entry, &entry_pivot) in row.iter_mut().zip(&pivot) {
+                        *entry = entry
+                            .checked_mul(pivot[col])
+                            .zip(entry_pivot.checked_mul(scale))
+                            .and_then(|(a, b)| a.checked_sub(b))
+                            .ok_or_else(|| anyhow!("joltage equations overflow i64"))?;
+                    }
+
+                    Self::normalize(row);
+                    row_origin.union(&origin);
+                }
+            }
+
+            Self::normalize(&mut matrix[r]);
+            pivots.push(col);
+        }
+
+        if let Some(r) = (pivots.len()..rows).find(|&r| matrix[r][cols] != 0) {
+            return Err(anyhow!(
+                "joltages {:?} contradict each other, as no combination of presses raises them \
+                 by the required amounts",
+                origins[r].ones(),
+            ));
+        }
+
+        let free = (0..cols)
+            .filter(|col| !pivots.contains(col))
+            .collect::<Vec<_>>();
+
+        let buttons = machine.buttons.clone();
+        let joltages = machine
+            .joltages
+            .iter()
+            .map(|&joltage| i64::try_from(joltage))
+            .collect::<Result<_, _>>()?;
+
+        let mut reduced = Self {
+            matrix,
+            pivots,
+            free,
+            resolved: Vec::new(),
+            remaining: Vec::new(),
+            buttons,
+            joltages,
+        };
+        reduced.order_free();
+
+        Ok(reduced)
+    }
+
+    /// Branches first on the free buttons with the fewest feasible presses, then groups pivot
+    /// rows by how many free buttons must be pressed before they are determined
+    fn order_free(&mut self) {
+        let all = (0..self.buttons.len()).collect::<Vec<_>>();
+        let mut free = self
+            .free
+            .iter()
+            .map(|&col| {
+                let span = self
+                    .range(&all, &self.joltages, col)
+                    .map_or(0, |(lo, hi)| hi - lo);
+
+                (span, col)
+            })
+            .collect::<Vec<_>>();
+        free.sort_unstable();
+        self.free = free.into_iter().map(|(_, col)| col).collect();
+
+        self.resolved = vec![Vec::new(); self.free.len() + 1];
+        for (r, row) in self.matrix.iter().enumerate().take(self.pivots.len()) {
+            let deps = self
+                .free
+                .iter()
+                .rposition(|&col| row[col] != 0)
+                .map_or(0, |k| k + 1);
+
+            self.resolved[deps].push(r);
+        }
+
+        // Buttons still unassigned once the first `k` free buttons and their rows are settled
+        let mut assigned = vec![false; self.buttons.len()];
+        self.remaining = (0..=self.free.len())
+            .map(|k| {
+                if k > 0 {
+                    assigned[self.free[k - 1]] = true;
+                }
+
+                for &r in &self.resolved[k] {
+                    assigned[self.pivots[r]] = true;
+                }
+
+                (0..self.buttons.len()).filter(|&b| !assigned[b]).collect()
+            })
+            .collect();
+    }
+
+    /// Minimizes `cost` over the real presses of `buttons` that raise each joltage by exactly
+    /// `residual`, or `None` if there are none
+    #[allow(clippy::cast_precision_loss)]
+    fn relaxation(
+        &self,
+        buttons: &[usize],
+        residual: &[i64],
+        cost: impl Fn(usize) -> f64,
+    ) -> Option<f64> {
+        let mut matrix = vec![vec![0.0; buttons.len()]; residual.len()];
+        for (j, &b) in buttons.iter().enumerate() {
+            for &i in &self.buttons[b] {
+                matrix[i][j] = 1.0;
+            }
+        }
+
+        let rhs = residual
+            .iter()
+            .map(|&joltage| joltage as f64)
+            .collect::<Vec<_>>();
+        let cost = buttons.iter().map(|&b| cost(b)).collect::<Vec<_>>();
+
+        self::simplex(&matrix, &rhs, &cost)
+    }
+
+    /// Bounds the whole presses of button `col` among the real solutions over `buttons`, which
+    /// keeps every pivot button's presses non-negative
+    #[allow(clippy::cast_possible_truncation)]
+    fn range(&self, buttons: &[usize], residual: &[i64], col: usize) -> Option<(i64, i64)> {
+        let unit = |sign| move |b| if b == col { sign } else { 0.0 };
+
+        let min = self.relaxation(buttons, residual, unit(1.0))?;
+        let max = -self.relaxation(buttons, residual, unit(-1.0))?;
+
+        Some((
+            (min - EPSILON).ceil() as i64,
+            (max + EPSILON).floor() as i64,
+        ))
+    }
+
+    /// Divides out the common factor of `row` to keep its entries small
+    fn normalize(row: &mut [i64]) {
+        fn gcd(a: i64, b: i64) -> i64 {
+            if b == 0 {
+                a.abs()
+            } else {
+                gcd(b, a % b)
+            }
+        }
+
+        let divisor = row.iter().fold(0, |divisor, &entry| gcd(divisor, entry));
+
+        if divisor > 1 {
+            for entry in row {
+                *entry /= divisor;
+            }
+        }
+    }
+
+    /// Assigns presses to the free buttons in turn, resolving pivot buttons as soon as their row
+    /// is fully determined. `residual` holds how much each joltage still needs from the buttons
+    /// yet to be pressed
+    fn search(
+        &self,
+        k: usize,
+        plan: &mut [i64],
+        residual: &[i64],
+        presses: i64,
+        plan_min: &mut Option<(i64, Vec<i64>)>,
+    ) {
+        let cols = self.buttons.len();
+        let mut residual = residual.to_vec();
+        let mut presses = presses;
+
+        for &r in &self.resolved[k] {
+            let row = &self.matrix[r];
+            let rhs = row[cols]
+                - self
+                    .free
+                    .iter()
+                    .take(k)
+                    .map(|&col| row[col] * plan[col])
+                    .sum::<i64>();
+            let pivot = row[self.pivots[r]];
+
+            if rhs % pivot != 0 || rhs / pivot < 0 {
+                return;
+            }
+
+            for &i in &self.buttons[self.pivots[r]] {
+                residual[i] -= rhs / pivot;
+            }
+
+            plan[self.pivots[r]] = rhs / pivot;
+            presses += rhs / pivot;
+        }
+
+        if residual.iter().any(|&joltage| joltage < 0) {
+            return;
+        }
+
+        // Whole presses cost at least the rounded up relaxation of the buttons left
+        let Some(presses_left) = self.relaxation(&self.remaining[k], &residual, |_| 1.0) else {
+            return;
+        };
+        #[allow(clippy::cast_possible_truncation)]
+        let presses_left = (presses_left - EPSILON).ceil() as i64;
+
+        if plan_min
+            .as_ref()
+            .is_some_and(|&(presses_min, _)| presses + presses_left >= presses_min)
+        {
+            return;
+        }
+
+        let Some(&col) = self.free.get(k) else {
+            *plan_min = Some((presses, plan.to_vec()));
+            return;
+        };
+
+        let Some((lo, hi)) = self.range(&self.remaining[k], &residual, col) else {
+            return;
+        };
+
+        for value in lo.max(0)..=hi {
+            for &i in &self.buttons[col] {
+                residual[i] -= value;
+            }
+
+            plan[col] = value;
+            self.search(k + 1, plan, &residual, presses + value, plan_min);
+
+            for &i in &self.buttons[col] {
+                residual[i] += value;
+            }
         }
     }
 }
 
-fn part1(machines: &[Machine]) -> usize {
-    machines.iter().map(Machine::configure_lights).sum()
+/// Minimizes `cost · x` subject to `matrix x = rhs` and `x >= 0`, where `rhs` is non-negative,
+/// returning `None` if infeasible. Two-phase dense simplex, entering by Bland's rule so it cannot
+/// cycle
+fn simplex(matrix: &[Vec<f64>], rhs: &[f64], cost: &[f64]) -> Option<f64> {
+    // Tableau rows are the constraints, then the cost and the phase one cost as reduced costs
+    // with the negated objective value in the last column
+    fn optimize(tableau: &mut [Vec<f64>], basis: &mut [usize], objective: usize, cols: usize) {
+        let last = tableau[0].len() - 1;
+
+        while let Some(col) = (0..cols).find(|&col| tableau[objective][col] < -EPSILON) {
+            let Some(row) = (0..basis.len())
+                .filter(|&row| tableau[row][col] > EPSILON)
+                .min_by(|&a, &b| {
+                    let ratio = |row: usize| tableau[row][last] / tableau[row][col];
+                    ratio(a).total_cmp(&ratio(b)).then(basis[a].cmp(&basis[b]))
+                })
+            else {
+                // Unbounded, which the joltage equations rule out
+                return;
+            };
+
+            pivot(tableau, basis, row, col);
+        }
+    }
+
+    fn pivot(tableau: &mut [Vec<f64>], basis: &mut [usize], row: usize, col: usize) {
+        let scale = tableau[row][col];
+        for entry in &mut tableau[row] {
+            *entry /= scale;
+        }
+
+        let pivot = tableau[row].clone();
+        for (r, other) in tableau.iter_mut().enumerate() {
+            let factor = other[col];
+
+            if r != row && factor != 0.0 {
+                for (entry, &entry_pivot) in other.iter_mut().zip(&pivot) {
+                    *entry = entry_pivot.mul_add(-factor, *entry);
+                }
+            }
+        }
+
+        basis[row] = col;
+    }
+
+    let (rows, cols) = (rhs.len(), cost.len());
+    let width = cols + rows + 1;
+
+    // Starts from an artificial variable per constraint, which phase one drives to zero
+    let mut tableau = vec![vec![0.0; width]; rows + 2];
+    for (r, (row, &value)) in matrix.iter().zip(rhs).enumerate() {
+        tableau[r][..cols].copy_from_slice(row);
+        tableau[r][cols + r] = 1.0;
+        tableau[r][width - 1] = value;
+
+        for (col, &entry) in row.iter().enumerate() {
+            tableau[rows + 1][col] -= entry;
+        }
+        tableau[rows + 1][width - 1] -= value;
+    }
+    tableau[rows][..cols].copy_from_slice(cost);
+
+    let mut basis = (cols..cols + rows).collect::<Vec<_>>();
+
+    optimize(&mut tableau, &mut basis, rows + 1, cols + rows);
+    if -tableau[rows + 1][width - 1] > EPSILON {
+        return None;
+    }
+
+    for row in 0..rows {
+        if basis[row] >= cols {
+            if let Some(col) = (0..cols).find(|&col| tableau[row][col].abs() > EPSILON) {
+                pivot(&mut tableau, &mut basis, row, col);
+            }
+        }
+    }
+
+    optimize(&mut tableau, &mut basis, rows, cols);
+
+    Some(-tableau[rows][width - 1])
 }
 
-fn part2(machines: &[Machine]) -> Result<usize> {
-    let presses = machines
-        .iter()
-        .map(Machine::configure_joltages)
-        .collect::<Result<Vec<_>>>()?
-        .into_iter()
-        .sum();
+impl<'a> Simulator<'a> {
+    fn new(machine: &'a Machine) -> Self {
+        Self {
+            machine,
+            lights: vec![Light::Off; machine.diagram.len()],
+            counters: vec![0; machine.joltages.len()],
+            presses: vec![0; machine.buttons.len()],
+        }
+    }
+
+    fn press(&mut self, b: usize, times: usize) -> Result<()> {
+        let button = self.machine.buttons.get(b).ok_or_else(|| {
+            anyhow!(
+                "no button {b}, the machine has {} buttons",
+                self.machine.buttons.len(),
+            )
+        })?;
+
+        // Checks every counter before touching any, so an overflowing press leaves no trace
+        let counters = button
+            .iter()
+            .map(|&i| {
+                self.counters[i]
+                    .checked_add(times)
+                    .ok_or_else(|| anyhow!("joltage {i} would overflow"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let presses = self.presses[b]
+            .checked_add(times)
+            .ok_or_else(|| anyhow!("presses of button {b} would overflow"))?;
+
+        for (&i, counter) in button.iter().zip(counters) {
+            if times % 2 == 1 {
+                self.lights[i] = self.lights[i].toggle();
+            }
+
+            self.counters[i] = counter;
+        }
+
+        self.presses[b] = presses;
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.machine);
+    }
+
+    /// Builds the machine left to configure the lights from here, which must toggle every light
+    /// that still differs from the diagram
+    fn remaining_lights(&self) -> Machine {
+        let diagram = self
+            .lights
+            .iter()
+            .zip(&self.machine.diagram)
+            .map(|(light, target)| {
+                if light == target {
+                    Light::Off
+                } else {
+                    Light::On
+                }
+            })
+            .collect();
+
+        Machine {
+            diagram,
+            buttons: self.machine.buttons.clone(),
+            joltages: self.machine.joltages.clone(),
+        }
+    }
+
+    /// Builds the machine left to configure the joltages from here, which must raise each counter
+    /// by however much it is short of its requirement
+    fn remaining_joltages(&self) -> Result<Machine> {
+        let joltages = self
+            .counters
+            .iter()
+            .zip(&self.machine.joltages)
+            .enumerate()
+            .map(|(i, (&counter, &target))| {
+                target.checked_sub(counter).ok_or_else(|| {
+                    anyhow!("joltage {i} is already at {counter}, past its requirement of {target}")
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Machine {
+            diagram: self.machine.diagram.clone(),
+            buttons: self.machine.buttons.clone(),
+            joltages,
+        })
+    }
+
+    fn show(&self) {
+        let lights = |lights: &[Light]| {
+            lights
+                .iter()
+                .map(|light| light.symbol())
+                .collect::<String>()
+        };
+
+        println!(
+            "Lights:   [{}] of [{}]",
+            lights(&self.lights),
+            lights(&self.machine.diagram),
+        );
+        println!(
+            "Joltages: {:?} of {:?}",
+            self.counters, self.machine.joltages
+        );
+
+        for (b, (button, presses)) in self.machine.buttons.iter().zip(&self.presses).enumerate() {
+            println!("Button {b}: {button:?}, pressed {presses} times");
+        }
+    }
+
+    /// Prints the fewest presses that finish the lights and the joltages from the current state
+    fn solve(&self, backend: &dyn Backend) {
+        let print = |what: &str, plan: Result<Vec<usize>>| match plan {
+            Ok(plan) => println!(
+                "{what}: {} more presses {plan:?}",
+                plan.iter().sum::<usize>(),
+            ),
+            Err(err) => println!("{what}: {err:#}"),
+        };
+
+        print("Lights", self.remaining_lights().configure_lights());
+        print(
+            "Joltages",
+            self.remaining_joltages()
+                .and_then(|machine| machine.configure_joltages(backend)),
+        );
+    }
+}
+
+impl FromStr for BackendKind {
+    type Err = Error;
+
+    fn from_str(backend: &str) -> Result<Self> {
+        match backend {
+            "elimination" => Ok(Self::Elimination),
+            "z3" => Ok(Self::Z3),
+            _ => Err(anyhow!("unknown backend '{backend}'")),
+        }
+    }
+}
+
+impl BackendKind {
+    fn backend(self) -> Result<Box<dyn Backend>> {
+        match self {
+            Self::Elimination => Ok(Box::new(Elimination)),
+            #[cfg(feature = "z3")]
+            Self::Z3 => Ok(Box::new(Z3)),
+            #[cfg(not(feature = "z3"))]
+            Self::Z3 => Err(anyhow!("built without the `z3` feature")),
+        }
+    }
+}
+
+impl Args {
+    fn parse() -> Result<Self> {
+        let mut args = env::args().skip(1);
+        let mut parsed = Self::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value for '{arg}'"))
+            };
+
+            match arg.as_str() {
+                "--backend" => parsed.backend = value()?.parse()?,
+                "--plans" => parsed.plans = true,
+                "--jobs" => parsed.jobs = Some(value()?.parse()?),
+                "--simulate" => parsed.simulate = Some(value()?.parse()?),
+                _ => return Err(anyhow!("unknown argument '{arg}'")),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+/// Operates `machine` by commands read from stdin, one per line
+fn simulate(machine: &Machine, backend: &dyn Backend) -> Result<()> {
+    const HELP: &str = "Commands: press <button> [times], show, reset, solve, help, quit";
+
+    let mut simulator = Simulator::new(machine);
 
-    Ok(presses)
+    println!("{HELP}");
+    simulator.show();
+
+    print!("> ");
+    io::stdout().flush()?;
+
+    for line in io::stdin().lines() {
+        let line = line?;
+
+        let mut words = line.split_ascii_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (None, _, _) => {}
+            (Some("press" | "p"), Some(b), times) => {
+                let pressed = b
+                    .parse()
+                    .map_err(Error::from)
+                    .and_then(|b| simulator.press(b, times.map_or(Ok(1), str::parse)?));
+
+                match pressed {
+                    Ok(()) => simulator.show(),
+                    Err(err) => println!("{err:#}"),
+                }
+            }
+            (Some("show"), None, None) => simulator.show(),
+            (Some("reset"), None, None) => {
+                simulator.reset();
+                simulator.show();
+            }
+            (Some("solve"), None, None) => simulator.solve(backend),
+            (Some("help"), None, None) => println!("{HELP}"),
+            (Some("quit" | "q"), None, None) => return Ok(()),
+            _ => println!("unknown command '{line}'\n{HELP}"),
+        }
+
+        print!("> ");
+        io::stdout().flush()?;
+    }
+
+    Ok(())
+}
+
+/// Solves every machine on `jobs` threads, each taking the next unclaimed machine, then reports
+/// all failures in line order rather than stopping at the first
+fn solve(
+    machines: &[Machine],
+    jobs: NonZeroUsize,
+    solve: impl Fn(&Machine) -> Result<Vec<usize>> + Sync,
+) -> Result<Vec<Vec<usize>>> {
+    let next = AtomicUsize::new(0);
+
+    let mut results = thread::scope(|scope| {
+        let workers = (0..jobs.get().min(machines.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(machine) = machines.get(i) else {
+                            return results;
+                        };
+
+                        results.push((i, solve(machine)));
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    results.sort_unstable_by_key(|&(i, _)| i);
+
+    let mut plans = Vec::new();
+    let mut errors = Vec::new();
+
+    for (i, result) in results {
+        match result {
+            Ok(plan) => plans.push(plan),
+            Err(err) => errors.push(format!("line {}: {err:#}", i + 1)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(plans)
+    } else {
+        Err(anyhow!(
+            "{} of {} machines failed:\n{}",
+            errors.len(),
+            machines.len(),
+            errors.join("\n"),
+        ))
+    }
+}
+
+fn part1(machines: &[Machine], jobs: NonZeroUsize) -> Result<Vec<Vec<usize>>> {
+    self::solve(machines, jobs, |machine| {
+        let plan = machine.configure_lights()?;
+        machine.verify_lights(&plan)?;
+
+        Ok(plan)
+    })
+}
+
+fn part2(
+    machines: &[Machine],
+    backend: &dyn Backend,
+    jobs: NonZeroUsize,
+) -> Result<Vec<Vec<usize>>> {
+    self::solve(machines, jobs, |machine| {
+        let plan = machine.configure_joltages(backend)?;
+        machine.verify_joltages(&plan)?;
+
+        Ok(plan)
+    })
 }
 
 fn main() -> Result<()> {
+    let args = Args::parse()?;
+    let backend = args.backend.backend()?;
+    let jobs = args.jobs.map_or_else(thread::available_parallelism, Ok)?;
+
     let machines = BufReader::new(File::open("in/day10.txt")?)
         .lines()
         .collect::<Result<Vec<_>, _>>()?
         .into_iter()
-        .map(|line| Machine::from_str(&line))
+        .zip(1..)
+        .map(|(machine, line)| {
+            Machine::from_str(&machine).map_err(|err| anyhow!("line {line}: {err}"))
+        })
         .collect::<Result<Vec<_>>>()?;
 
-    let part1 = self::part1(&machines);
-    let part2 = self::part2(&machines)?;
+    if let Some(line) = args.simulate {
+        let machine = line
+            .checked_sub(1)
+            .and_then(|i| machines.get(i))
+            .ok_or_else(|| anyhow!("no machine on line {line}"))?;
+
+        return self::simulate(machine, backend.as_ref());
+    }
+
+    let plans1 = self::part1(&machines, jobs)?;
+    let plans2 = self::part2(&machines, backend.as_ref(), jobs)?;
+
+    let part1 = plans1.iter().flatten().sum::<usize>();
+    let part2 = plans2.iter().flatten().sum::<usize>();
 
     println!("Part 1: {part1}");
     println!("Part 2: {part2}");
 
+    if args.plans {
+        for (line, (plan1, plan2)) in (1..).zip(plans1.iter().zip(&plans2)) {
+            println!("Line {line}: lights {plan1:?}, joltages {plan2:?}");
+        }
+    }
+
     assert_eq!(part1, 417);
     assert_eq!(part2, 16_765);
 
@@ -1,19 +1,69 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::env;
 use std::fs::File;
 use std::io::BufRead;
 use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Write;
+use std::mem;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use anyhow::anyhow;
 use anyhow::Error;
 use anyhow::Result;
 
+/// Device that every path ends at, which has no outputs of its own
+const SINK: &str = "out";
+
+/// How many paths `--paths` lists unless given a `--limit`
+const PATHS_LIMIT: usize = 100;
+
 #[derive(Debug)]
 struct Device {
     name: String,
     outputs: Vec<String>,
 }
 
+/// Device graph checked to be acyclic, with every output leading to a defined device or the sink.
+/// Devices are interned to dense ids in input order with the sink last, and their outputs are
+/// stored in compressed sparse row form
+#[derive(Debug)]
+struct Graph<'a> {
+    names: Vec<&'a str>,
+    ids: HashMap<&'a str, u32>,
+    offsets: Vec<usize>,
+    outputs: Vec<u32>,
+    order: Vec<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Visit {
+    InProgress,
+    Done,
+}
+
+/// Lazily enumerates the paths from a source to a target device that visit every waypoint,
+/// depth first in the order outputs are listed
+#[derive(Debug)]
+struct Paths<'g, 'a> {
+    graph: &'g Graph<'a>,
+    target: u32,
+    waypoints: Vec<usize>,
+    completions: Vec<Vec<bool>>,
+    stack: Vec<(u32, usize, usize)>,
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    dot: Option<PathBuf>,
+    paths: Option<String>,
+    to: Option<String>,
+    via: Vec<String>,
+    limit: Option<usize>,
+}
+
 impl FromStr for Device {
     type Err = Error;
 
@@ -36,66 +86,415 @@ impl FromStr for Device {
     }
 }
 
-fn dfs<'a>(
-    node: &'a str,
-    target: &'a str,
-    adj_list: &HashMap<&'a str, &'a Vec<String>>,
-    memo: &mut HashMap<(&'a str, &'a str), usize>,
-) -> usize {
-    if node == target {
-        return 1;
+impl<'a> Graph<'a> {
+    /// Validates `devices`, reporting problems by input line number
+    fn new(devices: &'a [Device]) -> Result<Self> {
+        let mut names = Vec::with_capacity(devices.len() + 1);
+        let mut ids = HashMap::with_capacity(devices.len() + 1);
+
+        for (line, device) in (1..).zip(devices) {
+            if device.name == SINK {
+                return Err(anyhow!("line {line}: sink '{SINK}' cannot have outputs"));
+            }
+
+            if let Some(id) = ids.insert(device.name.as_str(), u32::try_from(names.len())?) {
+                return Err(anyhow!(
+                    "line {line}: device '{}' is already defined on line {}",
+                    device.name,
+                    id + 1,
+                ));
+            }
+
+            names.push(device.name.as_str());
+        }
+
+        ids.insert(SINK, u32::try_from(names.len())?);
+        names.push(SINK);
+
+        let mut offsets = vec![0];
+        let mut outputs = Vec::new();
+        for (line, device) in (1..).zip(devices) {
+            for output in &device.outputs {
+                let &id = ids.get(output.as_str()).ok_or_else(|| {
+                    anyhow!(
+                        "line {line}: device '{}' outputs to unknown device '{output}'",
+                        device.name,
+                    )
+                })?;
+
+                outputs.push(id);
+            }
+
+            offsets.push(outputs.len());
+        }
+
+        // The sink has no outputs
+        offsets.push(outputs.len());
+
+        let mut graph = Self {
+            names,
+            ids,
+            offsets,
+            outputs,
+            order: Vec::new(),
+        };
+        graph.order = graph.topological_order()?;
+
+        Ok(graph)
+    }
+
+    fn id(&self, device: &str) -> Result<u32> {
+        self.ids
+            .get(device)
+            .copied()
+            .ok_or_else(|| anyhow!("unknown device '{device}'"))
+    }
+
+    fn outputs(&self, id: u32) -> &[u32] {
+        &self.outputs[self.offsets[id as usize]..self.offsets[id as usize + 1]]
+    }
+
+    /// Orders devices so each comes before all of its outputs. Depth-first search keeps the
+    /// current path on a stack, so reaching a device still on it closes a cycle
+    fn topological_order(&self) -> Result<Vec<u32>> {
+        let mut visits = vec![None; self.names.len()];
+        let mut order = Vec::with_capacity(self.names.len());
+
+        for root in (0..).take(self.names.len()) {
+            if visits[root as usize].is_some() {
+                continue;
+            }
+
+            let mut path = vec![(root, 0)];
+            visits[root as usize] = Some(Visit::InProgress);
+
+            while let Some(&mut (id, ref mut next)) = path.last_mut() {
+                let Some(&output) = self.outputs(id).get(*next) else {
+                    visits[id as usize] = Some(Visit::Done);
+                    order.push(id);
+                    path.pop();
+                    continue;
+                };
+                *next += 1;
+
+                match visits[output as usize] {
+                    Some(Visit::Done) => {}
+                    Some(Visit::InProgress) => {
+                        let start = path.iter().position(|&(id, _)| id == output).unwrap();
+                        let cycle = path[start..]
+                            .iter()
+                            .map(|&(id, _)| id)
+                            .chain([output])
+                            .map(|id| self.names[id as usize])
+                            .collect::<Vec<_>>();
+
+                        return Err(anyhow!("devices form a cycle: {}", cycle.join(" -> ")));
+                    }
+                    None => {
+                        visits[output as usize] = Some(Visit::InProgress);
+                        path.push((output, 0));
+                    }
+                }
+            }
+        }
+
+        order.reverse();
+
+        Ok(order)
     }
 
-    if let Some(&result) = memo.get(&(node, target)) {
-        return result;
+    /// Counts the paths from `source` to every device in one pass over the topological order
+    fn paths_from(&self, source: u32) -> Result<Vec<u128>> {
+        let mut paths = vec![0_u128; self.names.len()];
+        paths[source as usize] = 1;
+
+        for &id in &self.order {
+            let count = paths[id as usize];
+
+            if count > 0 {
+                for &output in self.outputs(id) {
+                    paths[output as usize] = paths[output as usize]
+                        .checked_add(count)
+                        .ok_or_else(|| self.overflow(source, output))?;
+                }
+            }
+        }
+
+        Ok(paths)
     }
 
-    let mut count = 0;
+    /// Counts the paths from every device to `target` in one pass over the reversed topological
+    /// order
+    fn paths_to(&self, target: u32) -> Result<Vec<u128>> {
+        let mut paths = vec![0_u128; self.names.len()];
+        paths[target as usize] = 1;
 
-    if let Some(&neighbors) = adj_list.get(node) {
-        for neighbor in neighbors {
-            count += dfs(neighbor, target, adj_list, memo);
+        for &id in self.order.iter().rev() {
+            if id != target {
+                paths[id as usize] = self
+                    .outputs(id)
+                    .iter()
+                    .try_fold(0_u128, |count, &output| {
+                        count.checked_add(paths[output as usize])
+                    })
+                    .ok_or_else(|| self.overflow(id, target))?;
+            }
         }
+
+        Ok(paths)
     }
 
-    memo.insert((node, target), count);
+    fn overflow(&self, source: u32, target: u32) -> Error {
+        anyhow!(
+            "number of paths from '{}' to '{}' exceeds {}",
+            self.names[source as usize],
+            self.names[target as usize],
+            u128::MAX,
+        )
+    }
+
+    /// Maps each device to its bit among `required`, or zero if it is not a waypoint
+    fn waypoints(&self, required: &[&str]) -> Result<Vec<usize>> {
+        if required.iter().collect::<HashSet<_>>().len() != required.len() {
+            return Err(anyhow!("waypoints {required:?} contain duplicates"));
+        }
+
+        if required.len() > 16 {
+            return Err(anyhow!(
+                "{} waypoints are too many, at most 16 are supported",
+                required.len(),
+            ));
+        }
+
+        let mut waypoints = vec![0; self.names.len()];
+        for (k, waypoint) in required.iter().enumerate() {
+            waypoints[self.id(waypoint)? as usize] = 1 << k;
+        }
+
+        Ok(waypoints)
+    }
+
+    /// Counts paths from `source` to `target` that visit every device in `required`, in any
+    /// order. Walks devices in topological order, counting the paths reaching each device
+    /// separately for every subset of waypoints visited so far
+    fn count_paths(&self, source: &str, target: &str, required: &[&str]) -> Result<u128> {
+        let (source, target) = (self.id(source)?, self.id(target)?);
+
+        if required.is_empty() {
+            return Ok(self.paths_from(source)?[target as usize]);
+        }
+
+        let waypoints = self.waypoints(required)?;
+
+        // Only devices reached so far hold a count per subset
+        let subsets = 1 << required.len();
+        let mut paths = vec![Vec::new(); self.names.len()];
+        paths[source as usize] = vec![0_u128; subsets];
+        paths[source as usize][waypoints[source as usize]] = 1;
+
+        for &id in &self.order {
+            if id == target || paths[id as usize].is_empty() {
+                continue;
+            }
 
-    count
+            let counts = mem::take(&mut paths[id as usize]);
+
+            for &output in self.outputs(id) {
+                let bit = waypoints[output as usize];
+                let paths = &mut paths[output as usize];
+
+                if paths.is_empty() {
+                    *paths = vec![0; subsets];
+                }
+
+                for (visited, &count) in counts.iter().enumerate() {
+                    paths[visited | bit] = paths[visited | bit]
+                        .checked_add(count)
+                        .ok_or_else(|| self.overflow(source, output))?;
+                }
+            }
+        }
+
+        Ok(paths[target as usize]
+            .get(subsets - 1)
+            .copied()
+            .unwrap_or_default())
+    }
 }
 
-fn part1(devices: &[Device]) -> usize {
-    let mut adj_list = HashMap::with_capacity(devices.len());
-    let mut memo = HashMap::new();
+impl Args {
+    fn parse() -> Result<Self> {
+        let mut args = env::args().skip(1);
+        let mut parsed = Self::default();
 
-    for device in devices {
-        adj_list.insert(device.name.as_str(), &device.outputs);
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value for '{arg}'"))
+            };
+
+            match arg.as_str() {
+                "--dot" => parsed.dot = Some(PathBuf::from(value()?)),
+                "--paths" => parsed.paths = Some(value()?),
+                "--to" => parsed.to = Some(value()?),
+                "--via" => parsed.via = value()?.split(',').map(str::to_string).collect(),
+                "--limit" => parsed.limit = Some(value()?.parse()?),
+                _ => return Err(anyhow!("unknown argument '{arg}'")),
+            }
+        }
+
+        Ok(parsed)
     }
+}
+
+/// Writes the device graph with each device labelled by its path counts from `you` and `svr`
+/// and to the sink, filling in the waypoints `fft` and `dac`
+fn write_dot(w: &mut impl Write, graph: &Graph) -> Result<()> {
+    let mut sources = Vec::new();
+    for source in ["you", "svr"] {
+        if let Ok(id) = graph.id(source) {
+            sources.push((source, graph.paths_from(id)?));
+        }
+    }
+
+    let paths_to = graph.paths_to(graph.id(SINK)?)?;
+
+    writeln!(w, "digraph devices {{")?;
 
-    self::dfs("you", "out", &adj_list, &mut memo)
+    for (id, name) in graph.names.iter().enumerate() {
+        write!(w, "    \"{name}\" [label=\"{name}")?;
+        for (source, paths_from) in &sources {
+            write!(w, "\\nfrom {source}: {}", paths_from[id])?;
+        }
+        write!(w, "\\nto {SINK}: {}\"", paths_to[id])?;
+
+        if ["fft", "dac"].contains(name) {
+            write!(w, ", style=filled, fillcolor=gold")?;
+        }
+
+        writeln!(w, "];")?;
+    }
+
+    for (id, name) in (0..).zip(&graph.names) {
+        for &output in graph.outputs(id) {
+            writeln!(w, "    \"{name}\" -> \"{}\";", graph.names[output as usize])?;
+        }
+    }
+
+    writeln!(w, "}}")?;
+
+    Ok(())
 }
 
-fn part2(devices: &[Device]) -> usize {
-    let mut adj_list = HashMap::with_capacity(devices.len());
-    let mut memo = HashMap::new();
+impl<'g, 'a> Paths<'g, 'a> {
+    /// Builds, for every device, which sets of waypoints some path from it to `target` covers,
+    /// so the search only descends into devices that can still complete a path
+    fn new(graph: &'g Graph<'a>, source: &str, target: &str, required: &[&str]) -> Result<Self> {
+        let (source, target) = (graph.id(source)?, graph.id(target)?);
+        let waypoints = graph.waypoints(required)?;
+        let subsets = 1 << required.len();
+
+        // Devices that cannot reach the target are left without any sets
+        let mut completions = vec![Vec::new(); graph.names.len()];
+        completions[target as usize] = vec![false; subsets];
+        completions[target as usize][waypoints[target as usize]] = true;
+
+        for &id in graph.order.iter().rev() {
+            if id == target {
+                continue;
+            }
+
+            let mut covered = vec![false; subsets];
+            for &output in graph.outputs(id) {
+                for (visited, &complete) in completions[output as usize].iter().enumerate() {
+                    covered[visited | waypoints[id as usize]] |= complete;
+                }
+            }
 
-    for device in devices {
-        adj_list.insert(device.name.as_str(), &device.outputs);
+            if covered.contains(&true) {
+                completions[id as usize] = covered;
+            }
+        }
+
+        // Any subset of a covered set is covered too
+        for covered in completions.iter_mut().filter(|covered| !covered.is_empty()) {
+            for k in 0..required.len() {
+                for visited in (0..subsets).filter(|visited| visited & 1 << k == 0) {
+                    covered[visited] |= covered[visited | 1 << k];
+                }
+            }
+        }
+
+        let mut paths = Self {
+            graph,
+            target,
+            waypoints,
+            completions,
+            stack: Vec::new(),
+        };
+
+        if paths.completes(source, 0) {
+            paths
+                .stack
+                .push((source, 0, paths.waypoints[source as usize]));
+        }
+
+        Ok(paths)
     }
 
-    let paths_fft_first = self::dfs("svr", "fft", &adj_list, &mut memo)
-        * self::dfs("fft", "dac", &adj_list, &mut memo)
-        * self::dfs("dac", "out", &adj_list, &mut memo);
+    /// Whether some path onwards from `id` visits every waypoint missing from `visited`
+    fn completes(&self, id: u32, visited: usize) -> bool {
+        let covered = &self.completions[id as usize];
+
+        !covered.is_empty() && covered[(covered.len() - 1) & !visited]
+    }
+}
+
+impl<'a> Iterator for Paths<'_, 'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let &mut (id, ref mut next, visited) = self.stack.last_mut()?;
+
+            if id == self.target {
+                let path = self
+                    .stack
+                    .iter()
+                    .map(|&(id, _, _)| self.graph.names[id as usize])
+                    .collect();
+                self.stack.pop();
+
+                return Some(path);
+            }
 
-    memo.clear();
+            let Some(&output) = self.graph.outputs(id).get(*next) else {
+                self.stack.pop();
+                continue;
+            };
+            *next += 1;
 
-    let paths_dac_first = self::dfs("svr", "dac", &adj_list, &mut memo)
-        * self::dfs("dac", "fft", &adj_list, &mut memo)
-        * self::dfs("fft", "out", &adj_list, &mut memo);
+            if self.completes(output, visited) {
+                self.stack
+                    .push((output, 0, visited | self.waypoints[output as usize]));
+            }
+        }
+    }
+}
+
+fn part1(graph: &Graph) -> Result<u128> {
+    let paths = graph.paths_to(graph.id(SINK)?)?;
 
-    paths_fft_first + paths_dac_first
+    Ok(paths[graph.id("you")? as usize])
+}
+
+fn part2(graph: &Graph) -> Result<u128> {
+    graph.count_paths("svr", SINK, &["fft", "dac"])
 }
 
 fn main() -> Result<()> {
+    let args = Args::parse()?;
+
     let devices = BufReader::new(File::open("in/day11.txt")?)
         .lines()
         .collect::<Result<Vec<_>, _>>()?
@@ -103,12 +502,30 @@ fn main() -> Result<()> {
         .map(|line| Device::from_str(&line))
         .collect::<Result<Vec<_>>>()?;
 
-    let part1 = self::part1(&devices);
-    let part2 = self::part2(&devices);
+    let graph = Graph::new(&devices)?;
+
+    if let Some(path) = &args.dot {
+        let mut w = BufWriter::new(File::create(path)?);
+        self::write_dot(&mut w, &graph)?;
+        w.flush()?;
+    }
+
+    let part1 = self::part1(&graph)?;
+    let part2 = self::part2(&graph)?;
 
     println!("Part 1: {part1}");
     println!("Part 2: {part2}");
 
+    if let Some(source) = &args.paths {
+        let target = args.to.as_deref().unwrap_or(SINK);
+        let required = args.via.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let paths = Paths::new(&graph, source, target, &required)?;
+        for (rank, path) in (1..).zip(paths.take(args.limit.unwrap_or(PATHS_LIMIT))) {
+            println!("Path {rank}: {}", path.join(" -> "));
+        }
+    }
+
     assert_eq!(part1, 796);
     assert_eq!(part2, 294_053_029_111_296);
 
@@ -1,5 +1,6 @@
 use std::cmp::Reverse;
 use std::collections::HashSet;
+use std::env;
 use std::fmt::Debug;
 use std::fmt::Write;
 use std::fs;
@@ -23,6 +24,13 @@ struct Present {
     shape: Shape,
 }
 
+/// Cells a present covers once placed, as bitmasks of the rows starting at `row`
+#[derive(Debug)]
+struct Placement {
+    row: usize,
+    masks: Vec<u128>,
+}
+
 #[derive(Debug)]
 struct Region {
     width: i32,
@@ -30,6 +38,35 @@ struct Region {
     quantities: Vec<usize>,
 }
 
+/// Dancing links over an exact cover matrix, as circular doubly linked lists of its ones. Node 0
+/// is the root, followed by one header per column, then the ones of each row in turn. Primary
+/// columns may be chained so each is covered by a row ranked above its predecessor's
+#[derive(Debug)]
+struct Dlx {
+    left: Vec<usize>,
+    right: Vec<usize>,
+    up: Vec<usize>,
+    down: Vec<usize>,
+    column: Vec<usize>,
+    sizes: Vec<usize>,
+    ranks: Vec<usize>,
+    prev: Vec<Option<usize>>,
+    next: Vec<Option<usize>>,
+    covered: Vec<Option<usize>>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+enum Solver {
+    #[default]
+    Backtrack,
+    Dlx,
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    solver: Solver,
+}
+
 impl Debug for Shape {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let row_min = self
@@ -118,6 +155,13 @@ impl FromStr for Region {
             .next()
             .ok_or_else(|| anyhow!("missing region width"))?
             .parse()?;
+
+        if width > 128 {
+            return Err(anyhow!(
+                "region width {width} exceeds the 128 columns a row bitmask holds"
+            ));
+        }
+
         let length = dimensions
             .next()
             .ok_or_else(|| anyhow!("missing region length"))?
@@ -195,8 +239,49 @@ impl Shape {
     }
 }
 
+impl Placement {
+    /// Packs in-bounds `cells` into row bitmasks
+    fn new(cells: &[(i32, i32)]) -> Self {
+        let row_min = cells.iter().map(|&(row, _)| row).min().unwrap_or_default();
+        let row_max = cells.iter().map(|&(row, _)| row).max().unwrap_or_default();
+
+        let mut masks = vec![0; (row_max - row_min + 1) as usize];
+        for &(row, col) in cells {
+            masks[(row - row_min) as usize] |= 1 << col;
+        }
+
+        Self {
+            row: row_min as usize,
+            masks,
+        }
+    }
+
+    fn fits(&self, grid: &[u128]) -> bool {
+        self.masks
+            .iter()
+            .zip(&grid[self.row..])
+            .all(|(mask, row)| mask & row == 0)
+    }
+
+    /// Places the present on `grid` if it fits, or removes it if it is already placed
+    fn toggle(&self, grid: &mut [u128]) {
+        for (mask, row) in self.masks.iter().zip(&mut grid[self.row..]) {
+            *row ^= mask;
+        }
+    }
+
+    /// Indices of the covered cells in a grid `width` columns wide, numbered row by row
+    fn cells(&self, width: usize) -> impl Iterator<Item = usize> + '_ {
+        (self.row..).zip(&self.masks).flat_map(move |(row, &mask)| {
+            (0..128)
+                .filter(move |col| mask >> col & 1 == 1)
+                .map(move |col| row * width + col)
+        })
+    }
+}
+
 impl Region {
-    fn is_feasible(&self, presents: &[Present]) -> bool {
+    fn is_feasible(&self, presents: &[Present], solver: Solver) -> bool {
         let area = self
             .quantities
             .iter()
@@ -237,7 +322,7 @@ impl Region {
                         });
 
                         if in_bounds {
-                            placements.push(placed);
+                            placements.push(Placement::new(&placed));
                         }
                     }
                 }
@@ -246,16 +331,44 @@ impl Region {
             all_placements.push((*group, placements));
         }
 
-        let mut grid = HashSet::new();
-        Self::dfs(&all_placements, 0, 0, usize::MAX, &mut grid)
+        match solver {
+            Solver::Backtrack => {
+                let mut grid = vec![0; self.length as usize];
+                Self::dfs(&all_placements, 0, 0, usize::MAX, &mut grid)
+            }
+            Solver::Dlx => self.exact_cover(&all_placements).search(),
+        }
+    }
+
+    /// Builds an exact cover matrix with a primary column per present, which must be placed
+    /// exactly once, and a secondary column per cell, which may be covered at most once. Copies
+    /// of the same present are chained to take their placements in order, like `dfs` does
+    fn exact_cover(&self, all_placements: &[(usize, Vec<Placement>)]) -> Dlx {
+        let width = self.width as usize;
+        let mut dlx = Dlx::new(all_placements.len(), self.length as usize * width);
+
+        for (present, (group, placements)) in all_placements.iter().enumerate() {
+            if present > 0 && all_placements[present - 1].0 == *group {
+                dlx.chain(present - 1, present);
+            }
+
+            for (rank, placement) in placements.iter().enumerate() {
+                let cells = placement
+                    .cells(width)
+                    .map(|cell| all_placements.len() + cell);
+                dlx.push_row(rank, iter::once(present).chain(cells));
+            }
+        }
+
+        dlx
     }
 
     fn dfs(
-        all_placements: &[(usize, Vec<Vec<(i32, i32)>>)],
+        all_placements: &[(usize, Vec<Placement>)],
         idx: usize,
         start: usize,
         prev: usize,
-        grid: &mut HashSet<(i32, i32)>,
+        grid: &mut [u128],
     ) -> bool {
         if idx == all_placements.len() {
             return true;
@@ -265,31 +378,213 @@ impl Region {
         let start = if *group == prev { start } else { 0 };
 
         for (i, placement) in placements.iter().enumerate().skip(start) {
-            if placement.iter().all(|pos| !grid.contains(pos)) {
-                grid.extend(placement.iter().copied());
+            if placement.fits(grid) {
+                placement.toggle(grid);
 
                 if Self::dfs(all_placements, idx + 1, i, *group, grid) {
                     return true;
                 }
 
-                for pos in placement {
-                    grid.remove(pos);
+                placement.toggle(grid);
+            }
+        }
+
+        false
+    }
+}
+
+impl Dlx {
+    /// Links the headers of the `primary` columns into the root's row, leaving the `secondary`
+    /// column headers on their own so they are never chosen to cover
+    fn new(primary: usize, secondary: usize) -> Self {
+        let headers = primary + secondary + 1;
+
+        let mut dlx = Self {
+            left: (0..headers).collect(),
+            right: (0..headers).collect(),
+            up: (0..headers).collect(),
+            down: (0..headers).collect(),
+            column: (0..headers).collect(),
+            sizes: vec![0; headers],
+            ranks: vec![0; headers],
+            prev: vec![None; headers],
+            next: vec![None; headers],
+            covered: vec![None; headers],
+        };
+
+        for c in 0..=primary {
+            dlx.left[c] = if c == 0 { primary } else { c - 1 };
+            dlx.right[c] = if c == primary { 0 } else { c + 1 };
+        }
+
+        dlx
+    }
+
+    /// Requires primary column `after` to be covered by a row ranked above the one covering
+    /// primary column `before`
+    fn chain(&mut self, before: usize, after: usize) {
+        self.next[before + 1] = Some(after + 1);
+        self.prev[after + 1] = Some(before + 1);
+    }
+
+    /// Appends a row ranked `rank` with a one in each of `columns`, numbered from 0 as the
+    /// primary then the secondary columns
+    fn push_row(&mut self, rank: usize, columns: impl Iterator<Item = usize>) {
+        let first = self.column.len();
+
+        for c in columns.map(|c| c + 1) {
+            let node = self.column.len();
+
+            self.left.push(if node == first { node } else { node - 1 });
+            self.right.push(first);
+            self.right[node - usize::from(node > first)] = node;
+            self.left[first] = node;
+
+            self.up.push(self.up[c]);
+            self.down.push(c);
+            self.down[self.up[c]] = node;
+            self.up[c] = node;
+
+            self.column.push(c);
+            self.sizes[c] += 1;
+            self.ranks.push(rank);
+        }
+    }
+
+    fn cover(&mut self, c: usize) {
+        self.right[self.left[c]] = self.right[c];
+        self.left[self.right[c]] = self.left[c];
+
+        let mut i = self.down[c];
+        while i != c {
+            let mut j = self.right[i];
+            while j != i {
+                self.down[self.up[j]] = self.down[j];
+                self.up[self.down[j]] = self.up[j];
+                self.sizes[self.column[j]] -= 1;
+                j = self.right[j];
+            }
+
+            i = self.down[i];
+        }
+    }
+
+    fn uncover(&mut self, c: usize) {
+        let mut i = self.up[c];
+        while i != c {
+            let mut j = self.left[i];
+            while j != i {
+                self.sizes[self.column[j]] += 1;
+                self.down[self.up[j]] = j;
+                self.up[self.down[j]] = j;
+                j = self.left[j];
+            }
+
+            i = self.up[i];
+        }
+
+        self.right[self.left[c]] = c;
+        self.left[self.right[c]] = c;
+    }
+
+    /// Algorithm X, covering the primary column with the fewest rows left first and skipping rows
+    /// out of order with a chained column already covered. Returns whether every primary column
+    /// can be covered
+    fn search(&mut self) -> bool {
+        let mut c = self.right[0];
+        if c == 0 {
+            return true;
+        }
+
+        let mut j = self.right[c];
+        while j != 0 {
+            if self.sizes[j] < self.sizes[c] {
+                c = j;
+            }
+
+            j = self.right[j];
+        }
+
+        self.cover(c);
+
+        let mut i = self.down[c];
+        while i != c {
+            let rank = self.ranks[i];
+            let ordered = self.prev[c].is_none_or(|p| self.covered[p].is_none_or(|r| r < rank))
+                && self.next[c].is_none_or(|n| self.covered[n].is_none_or(|r| r > rank));
+
+            if ordered {
+                let mut j = self.right[i];
+                while j != i {
+                    self.cover(self.column[j]);
+                    j = self.right[j];
+                }
+
+                self.covered[c] = Some(rank);
+                if self.search() {
+                    return true;
+                }
+                self.covered[c] = None;
+
+                let mut j = self.left[i];
+                while j != i {
+                    self.uncover(self.column[j]);
+                    j = self.left[j];
                 }
             }
+
+            i = self.down[i];
         }
 
+        self.uncover(c);
+
         false
     }
 }
 
-fn part1(presents: &[Present], regions: &[Region]) -> usize {
+impl FromStr for Solver {
+    type Err = Error;
+
+    fn from_str(solver: &str) -> Result<Self> {
+        match solver {
+            "backtrack" => Ok(Self::Backtrack),
+            "dlx" => Ok(Self::Dlx),
+            _ => Err(anyhow!("unknown solver '{solver}'")),
+        }
+    }
+}
+
+impl Args {
+    fn parse() -> Result<Self> {
+        let mut args = env::args().skip(1);
+        let mut parsed = Self::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value for '{arg}'"))
+            };
+
+            match arg.as_str() {
+                "--solver" => parsed.solver = value()?.parse()?,
+                _ => return Err(anyhow!("unknown argument '{arg}'")),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+fn part1(presents: &[Present], regions: &[Region], solver: Solver) -> usize {
     regions
         .iter()
-        .filter(|&region| region.is_feasible(presents))
+        .filter(|&region| region.is_feasible(presents, solver))
         .count()
 }
 
 fn main() -> Result<()> {
+    let args = Args::parse()?;
+
     let input = fs::read_to_string("in/day12.txt")?;
     let mut input = input.split("\n\n").collect::<Vec<_>>();
 
@@ -305,7 +600,7 @@ fn main() -> Result<()> {
         .collect::<Result<Vec<_>>>()?;
 
     let start = Instant::now();
-    let part1 = self::part1(&presents, &regions);
+    let part1 = self::part1(&presents, &regions, args.solver);
 
     println!(
         "Part 1: {part1} ({:?})",
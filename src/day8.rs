@@ -1,13 +1,25 @@
+use std::array;
 use std::cmp::Reverse;
 use std::collections::BinaryHeap;
 use std::collections::HashMap;
+use std::env;
 use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use anyhow::anyhow;
 use anyhow::Error;
 use anyhow::Result;
 
+/// How many of the closest pairs part 1 connects unless given `--connections`
+const CONNECTIONS: usize = 1_000;
+
+/// How many of the largest circuits part 1 multiplies unless given `--top`
+const TOP: usize = 3;
+
 #[derive(Debug)]
 struct JunctionBox {
     coords: [i64; 3],
@@ -36,46 +48,348 @@ impl FromStr for JunctionBox {
     }
 }
 
+/// How far apart two junction boxes are
+#[derive(Clone, Copy, Debug, Default)]
+enum Metric {
+    #[default]
+    SquaredEuclidean,
+    Manhattan,
+    Chebyshev,
+}
+
+/// Connection between junction boxes `i` and `j`
+#[derive(Debug)]
+struct Edge {
+    i: usize,
+    j: usize,
+    dist: u128,
+}
+
+/// Merge of clusters `left` and `right` into one of `len` boxes. Clusters `0..n` are the boxes
+/// themselves and the `k`th merge creates cluster `n + k`
+#[derive(Debug)]
+struct Merge {
+    left: usize,
+    right: usize,
+    len: usize,
+    dist: u128,
+}
+
+/// Single-linkage hierarchical clustering of the junction boxes
+#[derive(Debug)]
+struct Dendrogram {
+    leaves: usize,
+    merges: Vec<Merge>,
+}
+
+/// Where to cut a dendrogram into flat clusters
+#[derive(Clone, Copy, Debug)]
+enum Cut {
+    Dist(u128),
+    Count(usize),
+}
+
+#[derive(Debug, Default)]
+struct Args {
+    dot: Option<PathBuf>,
+    csv: Option<PathBuf>,
+    metric: Metric,
+    linkage: Option<PathBuf>,
+    cut: Option<Cut>,
+    connections: Option<usize>,
+    top: Option<usize>,
+}
+
+impl Args {
+    fn parse() -> Result<Self> {
+        let mut args = env::args().skip(1);
+        let mut parsed = Self::default();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value for '{arg}'"))
+            };
+
+            match arg.as_str() {
+                "--dot" => parsed.dot = Some(PathBuf::from(value()?)),
+                "--csv" => parsed.csv = Some(PathBuf::from(value()?)),
+                "--metric" => parsed.metric = value()?.parse()?,
+                "--linkage" => parsed.linkage = Some(PathBuf::from(value()?)),
+                "--cut-dist" => parsed.cut = Some(Cut::Dist(value()?.parse()?)),
+                "--cut-count" => parsed.cut = Some(Cut::Count(value()?.parse()?)),
+                "--connections" => parsed.connections = Some(value()?.parse()?),
+                "--top" => parsed.top = Some(value()?.parse()?),
+                _ => return Err(anyhow!("unknown argument '{arg}'")),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+impl FromStr for Metric {
+    type Err = Error;
+
+    fn from_str(metric: &str) -> Result<Self> {
+        match metric {
+            "squared-euclidean" => Ok(Self::SquaredEuclidean),
+            "manhattan" => Ok(Self::Manhattan),
+            "chebyshev" => Ok(Self::Chebyshev),
+            _ => Err(anyhow!("unknown metric '{metric}'")),
+        }
+    }
+}
+
+impl Metric {
+    /// Distance along a single axis, which never exceeds the full distance
+    fn axis(self, delta: u64) -> u128 {
+        match self {
+            Self::SquaredEuclidean => u128::from(delta).pow(2),
+            Self::Manhattan | Self::Chebyshev => u128::from(delta),
+        }
+    }
+
+    /// Combines the distances along each axis into the full distance
+    fn norm(self, deltas: impl Iterator<Item = u64>) -> u128 {
+        let deltas = deltas.map(|delta| self.axis(delta));
+
+        match self {
+            Self::SquaredEuclidean | Self::Manhattan => deltas.sum(),
+            Self::Chebyshev => deltas.max().unwrap_or_default(),
+        }
+    }
+
+    /// Fails if the distance between any two of `boxes` may overflow a `u128`
+    fn check(self, boxes: &[JunctionBox]) -> Result<()> {
+        let mut dist_max = 0_u128;
+
+        for axis in 0..3 {
+            let coords = boxes.iter().map(|b| b.coords[axis]);
+            let span = coords
+                .clone()
+                .max()
+                .unwrap_or_default()
+                .abs_diff(coords.min().unwrap_or_default());
+
+            dist_max =
+                match self {
+                    Self::SquaredEuclidean | Self::Manhattan => dist_max
+                        .checked_add(self.axis(span))
+                        .ok_or_else(|| anyhow!("coordinates too far apart for {self:?}"))?,
+                    Self::Chebyshev => dist_max.max(self.axis(span)),
+                };
+        }
+
+        Ok(())
+    }
+}
+
 impl JunctionBox {
-    fn dist(&self, other: &Self) -> i64 {
-        self.coords
-            .iter()
-            .zip(&other.coords)
-            .map(|(coord_i, coord_j)| coord_i - coord_j)
-            .map(|delta| delta.pow(2))
-            .sum()
+    /// Only overflows if `metric` has not been checked against every box
+    fn dist(&self, other: &Self, metric: Metric) -> u128 {
+        metric.norm(
+            self.coords
+                .iter()
+                .zip(&other.coords)
+                .map(|(coord_i, coord_j)| coord_i.abs_diff(*coord_j)),
+        )
     }
 }
 
-/// Union find
-fn part1(boxes: &[JunctionBox]) -> usize {
-    fn find(parents: &mut [usize], i: usize) -> usize {
-        if parents[i] != i {
-            parents[i] = find(parents, parents[i]);
+/// Implicit k-d tree over box indices, split on the median of each axis in turn
+struct KdTree {
+    indices: Vec<usize>,
+}
+
+impl KdTree {
+    fn new(boxes: &[JunctionBox]) -> Self {
+        fn build(boxes: &[JunctionBox], indices: &mut [usize], axis: usize) {
+            if indices.len() <= 1 {
+                return;
+            }
+
+            let mid = indices.len() / 2;
+            indices.select_nth_unstable_by_key(mid, |&i| boxes[i].coords[axis]);
+
+            let (left, right) = indices.split_at_mut(mid);
+            build(boxes, left, (axis + 1) % 3);
+            build(boxes, &mut right[1..], (axis + 1) % 3);
         }
 
-        parents[i]
+        let mut indices = (0..boxes.len()).collect::<Vec<_>>();
+        build(boxes, &mut indices, 0);
+
+        Self { indices }
     }
 
-    let mut pairs = Vec::new();
-    for i in 0..boxes.len() - 1 {
-        for j in i + 1..boxes.len() {
-            pairs.push((i, j, boxes[i].dist(&boxes[j])));
+    /// The `k` nearest boxes to `target` (itself included), ordered by `(dist, idx)`
+    fn nearest(
+        &self,
+        boxes: &[JunctionBox],
+        metric: Metric,
+        target: &JunctionBox,
+        k: usize,
+    ) -> Vec<(u128, usize)> {
+        fn search(
+            boxes: &[JunctionBox],
+            metric: Metric,
+            target: &JunctionBox,
+            indices: &[usize],
+            axis: usize,
+            k: usize,
+            nearest: &mut BinaryHeap<(u128, usize)>,
+        ) {
+            if indices.is_empty() {
+                return;
+            }
+
+            let mid = indices.len() / 2;
+            let node = indices[mid];
+            let candidate = (target.dist(&boxes[node], metric), node);
+
+            if nearest.len() < k {
+                nearest.push(candidate);
+            } else if nearest.peek().is_some_and(|&worst| candidate < worst) {
+                nearest.pop();
+                nearest.push(candidate);
+            }
+
+            let coord = boxes[node].coords[axis];
+            let delta = metric.axis(target.coords[axis].abs_diff(coord));
+            let (near, far) = if target.coords[axis] < coord {
+                (&indices[..mid], &indices[mid + 1..])
+            } else {
+                (&indices[mid + 1..], &indices[..mid])
+            };
+
+            search(boxes, metric, target, near, (axis + 1) % 3, k, nearest);
+
+            // Ties must still be explored so results are exact under `(dist, idx)`
+            if nearest.len() < k || nearest.peek().is_some_and(|&(worst, _)| delta <= worst) {
+                search(boxes, metric, target, far, (axis + 1) % 3, k, nearest);
+            }
+        }
+
+        let mut nearest = BinaryHeap::with_capacity(k + 1);
+        search(boxes, metric, target, &self.indices, 0, k, &mut nearest);
+
+        nearest.into_sorted_vec()
+    }
+}
+
+#[derive(Default)]
+struct Neighbors {
+    nearest: Vec<(u128, usize)>,
+    next: usize,
+}
+
+/// Lazily yields pairs `(i, j, dist)` with `i < j`, ordered by `(dist, i, j)`
+struct NearestPairs<'a> {
+    boxes: &'a [JunctionBox],
+    metric: Metric,
+    tree: KdTree,
+    neighbors: Vec<Neighbors>,
+    pairs: BinaryHeap<Reverse<(u128, usize, usize)>>,
+}
+
+impl<'a> NearestPairs<'a> {
+    const NEIGHBORS_MIN: usize = 8;
+
+    fn new(boxes: &'a [JunctionBox], metric: Metric) -> Result<Self> {
+        metric.check(boxes)?;
+
+        let mut pairs = Self {
+            boxes,
+            metric,
+            tree: KdTree::new(boxes),
+            neighbors: Vec::new(),
+            pairs: BinaryHeap::with_capacity(boxes.len()),
+        };
+
+        pairs.neighbors.resize_with(boxes.len(), Neighbors::default);
+
+        for i in 0..boxes.len() {
+            if let Some((dist, j)) = pairs.next_neighbor(i) {
+                pairs.pairs.push(Reverse((dist, i, j)));
+            }
+        }
+
+        Ok(pairs)
+    }
+
+    /// Next nearest neighbor `j > i` of box `i`, growing its k-NN query as needed
+    fn next_neighbor(&mut self, i: usize) -> Option<(u128, usize)> {
+        let neighbors = &mut self.neighbors[i];
+
+        loop {
+            while let Some(&(dist, j)) = neighbors.nearest.get(neighbors.next) {
+                neighbors.next += 1;
+
+                if j > i {
+                    return Some((dist, j));
+                }
+            }
+
+            if neighbors.nearest.len() == self.boxes.len() {
+                return None;
+            }
+
+            let k = (neighbors.nearest.len() * 2)
+                .max(Self::NEIGHBORS_MIN)
+                .min(self.boxes.len());
+            neighbors.nearest = self
+                .tree
+                .nearest(self.boxes, self.metric, &self.boxes[i], k);
         }
     }
+}
+
+impl Iterator for NearestPairs<'_> {
+    type Item = (usize, usize, u128);
 
-    pairs.select_nth_unstable_by_key(999, |&(_, _, dist)| dist);
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((dist, i, j)) = self.pairs.pop()?;
+
+        if let Some((dist, j)) = self.next_neighbor(i) {
+            self.pairs.push(Reverse((dist, i, j)));
+        }
+
+        Some((i, j, dist))
+    }
+}
+
+/// Union find over the `connections` shortest pairs, multiplying the sizes of the `top` largest
+/// circuits
+fn part1(boxes: &[JunctionBox], metric: Metric, connections: usize, top: usize) -> Result<usize> {
+    fn find(parents: &mut [usize], i: usize) -> usize {
+        if parents[i] != i {
+            parents[i] = find(parents, parents[i]);
+        }
+
+        parents[i]
+    }
 
-    let shortest = &pairs[..1000];
     let mut parents = (0..boxes.len()).collect::<Vec<_>>();
+    let mut connected = 0;
 
-    for &(i, j, _) in shortest {
+    for (i, j, _) in NearestPairs::new(boxes, metric)?.take(connections) {
         let root_i = find(&mut parents, i);
         let root_j = find(&mut parents, j);
 
         if root_i != root_j {
             parents[root_i] = root_j;
         }
+
+        connected += 1;
+    }
+
+    if connected < connections {
+        return Err(anyhow!(
+            "cannot make {connections} connections between {} junction boxes, only {connected} \
+             pairs exist",
+            boxes.len(),
+        ));
     }
 
     let mut lens = HashMap::<usize, usize>::new();
@@ -84,13 +398,271 @@ fn part1(boxes: &[JunctionBox]) -> usize {
     }
 
     let mut lens = lens.into_values().collect::<Vec<_>>();
-    lens.select_nth_unstable_by(2, |i, j| j.cmp(i));
+    if lens.len() < top {
+        return Err(anyhow!(
+            "cannot take the {top} largest circuits, only {} exist",
+            lens.len(),
+        ));
+    }
+
+    if let Some(nth) = top.checked_sub(1) {
+        lens.select_nth_unstable_by(nth, |i, j| j.cmp(i));
+    }
+
+    Ok(lens.into_iter().take(top).product())
+}
+
+/// Node over the boxes at `indices[lo..hi]`, with the corners of their bounding box
+#[derive(Clone, Copy)]
+struct Node {
+    lo: usize,
+    hi: usize,
+    corners: [[i64; 3]; 2],
+    children: Option<[usize; 2]>,
+}
+
+/// Dual-tree Borůvka's MST, where every round joins each circuit to its nearest box outside it.
+/// Nodes are labelled with the circuit all their boxes share, if any, and bounded by the
+/// farthest any of their circuits still has to look, so pairs of nodes that cannot bring a
+/// circuit closer are skipped whole
+struct Boruvka<'a> {
+    boxes: &'a [JunctionBox],
+    metric: Metric,
+    indices: Vec<usize>,
+    nodes: Vec<Node>,
+    circuits: Vec<usize>,
+    labels: Vec<usize>,
+    bounds: Vec<u128>,
+    nearest: Vec<Option<(u128, usize, usize)>>,
+}
+
+impl<'a> Boruvka<'a> {
+    /// Most boxes in a leaf, which are compared pairwise against another leaf
+    const LEAF_MAX: usize = 16;
+
+    /// Label of a node whose boxes span several circuits
+    const MIXED: usize = usize::MAX;
+
+    fn new(boxes: &'a [JunctionBox], metric: Metric) -> Self {
+        let mut boruvka = Self {
+            boxes,
+            metric,
+            indices: (0..boxes.len()).collect(),
+            nodes: Vec::new(),
+            circuits: (0..boxes.len()).collect(),
+            labels: Vec::new(),
+            bounds: Vec::new(),
+            nearest: vec![None; boxes.len()],
+        };
+
+        if !boxes.is_empty() {
+            boruvka.build(0, boxes.len());
+        }
+
+        boruvka.labels = vec![Self::MIXED; boruvka.nodes.len()];
+        boruvka.bounds = vec![u128::MAX; boruvka.nodes.len()];
+
+        boruvka
+    }
+
+    /// Adds the nodes over `indices[lo..hi]` in preorder, returning the id of their root. Nodes
+    /// split on the median of their widest axis, so distant clusters part before any is cut
+    fn build(&mut self, lo: usize, hi: usize) -> usize {
+        let mut corners = [self.boxes[self.indices[lo]].coords; 2];
+        for &i in &self.indices[lo..hi] {
+            let [min, max] = corners;
+            let coords = self.boxes[i].coords;
+
+            corners = [
+                array::from_fn(|axis| min[axis].min(coords[axis])),
+                array::from_fn(|axis| max[axis].max(coords[axis])),
+            ];
+        }
+
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            lo,
+            hi,
+            corners,
+            children: None,
+        });
+
+        if hi - lo > Self::LEAF_MAX {
+            let [min, max] = corners;
+            let axis = (0..3)
+                .max_by_key(|&axis| min[axis].abs_diff(max[axis]))
+                .unwrap_or_default();
+
+            let mid = lo + (hi - lo) / 2;
+            self.indices[lo..hi]
+                .select_nth_unstable_by_key(mid - lo, |&i| self.boxes[i].coords[axis]);
+
+            self.nodes[id].children = Some([self.build(lo, mid), self.build(mid, hi)]);
+        }
+
+        id
+    }
+
+    /// Starts a round from the circuit of every box, with no nearest boxes found yet
+    fn reset(&mut self, circuits: impl Iterator<Item = usize>) {
+        for (circuit, root) in self.circuits.iter_mut().zip(circuits) {
+            *circuit = root;
+        }
+
+        // Children come after their parent in preorder
+        for id in (0..self.nodes.len()).rev() {
+            let Node {
+                lo, hi, children, ..
+            } = self.nodes[id];
+
+            self.labels[id] = match children {
+                Some([left, right]) if self.labels[left] == self.labels[right] => self.labels[left],
+                Some(_) => Self::MIXED,
+                None => {
+                    let circuit = self.circuits[self.indices[lo]];
+
+                    if self.indices[lo..hi]
+                        .iter()
+                        .all(|&i| self.circuits[i] == circuit)
+                    {
+                        circuit
+                    } else {
+                        Self::MIXED
+                    }
+                }
+            };
+        }
+
+        self.bounds.fill(u128::MAX);
+        self.nearest.fill(None);
+    }
+
+    /// Lower bound on the distance between any box under node `q` and any box under node `r`
+    fn gap(&self, q: usize, r: usize) -> u128 {
+        let [q_min, q_max] = self.nodes[q].corners;
+        let [r_min, r_max] = self.nodes[r].corners;
+
+        self.metric.norm((0..3).map(|axis| {
+            if q_max[axis] < r_min[axis] {
+                q_max[axis].abs_diff(r_min[axis])
+            } else if r_max[axis] < q_min[axis] {
+                r_max[axis].abs_diff(q_min[axis])
+            } else {
+                0
+            }
+        }))
+    }
+
+    /// Tightens the nearest box outside the circuit of every box under node `q` to the boxes
+    /// under node `r`, which lie at least `gap` away, ordering pairs `(dist, i, j)` the way
+    /// Kruskal's takes them
+    fn search(&mut self, q: usize, r: usize, gap: u128) {
+        // Ties must still be explored so results are exact under `(dist, i, j)`
+        if gap > self.bound(q) || self.labels[q] != Self::MIXED && self.labels[q] == self.labels[r]
+        {
+            return;
+        }
+
+        match (self.nodes[q].children, self.nodes[r].children) {
+            (None, None) => self.compare(q, r),
+            (None, Some(others)) => {
+                for (gap, r) in self.closest(q, others) {
+                    self.search(q, r, gap);
+                }
+            }
+            (Some(children), None) => {
+                for child in children {
+                    self.search(child, r, self.gap(child, r));
+                }
+
+                self.bounds[q] = self.bound(children[0]).max(self.bound(children[1]));
+            }
+            (Some(children), Some(others)) => {
+                for child in children {
+                    for (gap, r) in self.closest(child, others) {
+                        self.search(child, r, gap);
+                    }
+                }
+
+                self.bounds[q] = self.bound(children[0]).max(self.bound(children[1]));
+            }
+        }
+    }
+
+    /// Farthest any box under node `q` still has to look, which is current for a node within one
+    /// circuit and otherwise as of its last search
+    fn bound(&self, q: usize) -> u128 {
+        match self.labels[q] {
+            Self::MIXED => self.bounds[q],
+            circuit => self.nearest[circuit].map_or(u128::MAX, |(dist, _, _)| dist),
+        }
+    }
+
+    /// Pairs nodes `rs` with their gap to node `q`, closest first
+    fn closest(&self, q: usize, rs: [usize; 2]) -> [(u128, usize); 2] {
+        let mut closest = rs.map(|r| (self.gap(q, r), r));
+        if closest[1].0 < closest[0].0 {
+            closest.swap(0, 1);
+        }
+
+        closest
+    }
+
+    /// Compares every box under leaf `q` with every box under leaf `r`
+    fn compare(&mut self, q: usize, r: usize) {
+        let Self {
+            boxes,
+            metric,
+            indices,
+            nodes,
+            circuits,
+            bounds,
+            nearest,
+            ..
+        } = self;
+
+        let [r_min, r_max] = nodes[r].corners;
+
+        for &i in &indices[nodes[q].lo..nodes[q].hi] {
+            let circuit = circuits[i];
+            let gap = metric.norm((0..3).map(|axis| {
+                let coord = boxes[i].coords[axis];
+
+                if coord < r_min[axis] {
+                    coord.abs_diff(r_min[axis])
+                } else if coord > r_max[axis] {
+                    coord.abs_diff(r_max[axis])
+                } else {
+                    0
+                }
+            }));
+
+            if nearest[circuit].is_some_and(|(dist, _, _)| gap > dist) {
+                continue;
+            }
+
+            for &j in &indices[nodes[r].lo..nodes[r].hi] {
+                if circuits[j] == circuit {
+                    continue;
+                }
+
+                let candidate = (boxes[i].dist(&boxes[j], *metric), i.min(j), i.max(j));
+                if nearest[circuit].is_none_or(|nearest| candidate < nearest) {
+                    nearest[circuit] = Some(candidate);
+                }
+            }
+        }
 
-    lens.into_iter().take(3).product()
+        bounds[q] = indices[nodes[q].lo..nodes[q].hi]
+            .iter()
+            .map(|&i| nearest[circuits[i]].map_or(u128::MAX, |(dist, _, _)| dist))
+            .max()
+            .unwrap_or_default();
+    }
 }
 
-/// Kruskal's MST
-fn part2(boxes: &[JunctionBox]) -> i64 {
+/// Minimum spanning tree, with edges in the order Kruskal's would merge their circuits
+fn minimum_spanning_tree(boxes: &[JunctionBox], metric: Metric) -> Result<Vec<Edge>> {
     fn find(parents: &mut [usize], i: usize) -> usize {
         if parents[i] != i {
             parents[i] = find(parents, parents[i]);
@@ -98,61 +670,246 @@ fn part2(boxes: &[JunctionBox]) -> i64 {
         parents[i]
     }
 
-    let mut pairs = Vec::new();
-    for i in 0..boxes.len() - 1 {
-        for j in i + 1..boxes.len() {
-            pairs.push((i, j, boxes[i].dist(&boxes[j])));
+    metric.check(boxes)?;
+
+    let mut boruvka = Boruvka::new(boxes, metric);
+    let mut parents = (0..boxes.len()).collect::<Vec<_>>();
+    let mut lens = vec![1; boxes.len()];
+    let mut edges = Vec::with_capacity(boxes.len().saturating_sub(1));
+
+    while edges.len() + 1 < boxes.len() {
+        boruvka.reset((0..boxes.len()).map(|i| find(&mut parents, i)));
+        boruvka.search(0, 0, 0);
+
+        for &(dist, i, j) in boruvka.nearest.iter().flatten() {
+            let root_i = find(&mut parents, i);
+            let root_j = find(&mut parents, j);
+
+            if root_i != root_j {
+                if lens[root_i] < lens[root_j] {
+                    parents[root_i] = root_j;
+                    lens[root_j] += lens[root_i];
+                } else {
+                    parents[root_j] = root_i;
+                    lens[root_i] += lens[root_j];
+                }
+
+                edges.push(Edge { i, j, dist });
+            }
         }
     }
 
-    let mut pairs = pairs
-        .into_iter()
-        .map(|(i, j, d)| Reverse((d, i, j)))
-        .collect::<BinaryHeap<_>>();
+    // Pairs are totally ordered by `(dist, i, j)`, so this is the tree Kruskal's would build
+    edges.sort_unstable_by_key(|edge| (edge.dist, edge.i, edge.j));
 
-    let mut parents = (0..boxes.len()).collect::<Vec<_>>();
-    let mut lens = vec![1; boxes.len()];
-    let mut component_len_max = 1;
+    Ok(edges)
+}
 
-    while let Some(Reverse((_, i, j))) = pairs.pop() {
-        let root_i = find(&mut parents, i);
-        let root_j = find(&mut parents, j);
+impl Dendrogram {
+    /// Kruskal's MST merges components in the same order as single linkage
+    fn new(boxes: &[JunctionBox], edges: &[Edge]) -> Self {
+        fn find(parents: &mut [usize], i: usize) -> usize {
+            if parents[i] != i {
+                parents[i] = find(parents, parents[i]);
+            }
+            parents[i]
+        }
 
-        if root_i != root_j {
-            if lens[root_i] < lens[root_j] {
-                parents[root_i] = root_j;
-                lens[root_j] += lens[root_i];
-                component_len_max = component_len_max.max(lens[root_j]);
-            } else {
-                parents[root_j] = root_i;
-                lens[root_i] += lens[root_j];
-                component_len_max = component_len_max.max(lens[root_i]);
+        let mut parents = (0..boxes.len()).collect::<Vec<_>>();
+        let mut clusters = (0..boxes.len()).collect::<Vec<_>>();
+        let mut lens = vec![1; boxes.len()];
+        let mut merges = Vec::with_capacity(edges.len());
+
+        for &Edge { i, j, dist } in edges {
+            let root_i = find(&mut parents, i);
+            let root_j = find(&mut parents, j);
+
+            parents[root_i] = root_j;
+            lens[root_j] += lens[root_i];
+
+            merges.push(Merge {
+                left: clusters[root_i],
+                right: clusters[root_j],
+                len: lens[root_j],
+                dist,
+            });
+
+            clusters[root_j] = boxes.len() + merges.len() - 1;
+        }
+
+        Self {
+            leaves: boxes.len(),
+            merges,
+        }
+    }
+
+    /// Flat clusters of box indices, largest first
+    fn cut(&self, cut: Cut) -> Result<Vec<Vec<usize>>> {
+        fn find(parents: &mut [usize], i: usize) -> usize {
+            if parents[i] != i {
+                parents[i] = find(parents, parents[i]);
+            }
+            parents[i]
+        }
+
+        let merges = match cut {
+            Cut::Dist(dist) => self.merges.partition_point(|merge| merge.dist <= dist),
+            Cut::Count(count) => {
+                if count == 0 || count > self.leaves {
+                    return Err(anyhow!(
+                        "cannot cut {} junction boxes into {count} clusters",
+                        self.leaves,
+                    ));
+                }
+
+                self.leaves - count
             }
+        };
+
+        // Any box in each cluster stands in for it during union find
+        let mut boxes = (0..self.leaves).collect::<Vec<_>>();
+        let mut parents = (0..self.leaves).collect::<Vec<_>>();
+
+        for merge in &self.merges[..merges] {
+            let root_left = find(&mut parents, boxes[merge.left]);
+            let root_right = find(&mut parents, boxes[merge.right]);
+
+            parents[root_left] = root_right;
+            boxes.push(root_right);
         }
 
-        if component_len_max == boxes.len() {
-            return boxes[i].coords[0] * boxes[j].coords[0];
+        let mut clusters = HashMap::<usize, Vec<usize>>::new();
+        for i in 0..self.leaves {
+            clusters.entry(find(&mut parents, i)).or_default().push(i);
         }
+
+        let mut clusters = clusters.into_values().collect::<Vec<_>>();
+        clusters.sort_unstable_by(|i, j| j.len().cmp(&i.len()).then_with(|| i.cmp(j)));
+
+        Ok(clusters)
+    }
+}
+
+fn write_dot(w: &mut impl Write, boxes: &[JunctionBox], edges: &[Edge]) -> Result<()> {
+    writeln!(w, "graph mst {{")?;
+
+    for (i, JunctionBox { coords: [x, y, z] }) in boxes.iter().enumerate() {
+        writeln!(w, "    {i} [label=\"{x},{y},{z}\"];")?;
+    }
+
+    for (order, Edge { i, j, dist }) in edges.iter().enumerate() {
+        writeln!(w, "    {i} -- {j} [label=\"#{order}: {dist}\"];")?;
     }
 
-    unreachable!()
+    writeln!(w, "}}")?;
+
+    Ok(())
+}
+
+/// Writes merges in the same layout as `SciPy`'s linkage matrix
+fn write_linkage(w: &mut impl Write, dendrogram: &Dendrogram) -> Result<()> {
+    writeln!(w, "left,right,dist,len")?;
+
+    for Merge {
+        left,
+        right,
+        len,
+        dist,
+    } in &dendrogram.merges
+    {
+        writeln!(w, "{left},{right},{dist},{len}")?;
+    }
+
+    Ok(())
+}
+
+fn write_csv(w: &mut impl Write, boxes: &[JunctionBox], edges: &[Edge]) -> Result<()> {
+    writeln!(w, "order,i,j,x_i,y_i,z_i,x_j,y_j,z_j,dist")?;
+
+    for (order, Edge { i, j, dist }) in edges.iter().enumerate() {
+        let [x_i, y_i, z_i] = boxes[*i].coords;
+        let [x_j, y_j, z_j] = boxes[*j].coords;
+
+        writeln!(
+            w,
+            "{order},{i},{j},{x_i},{y_i},{z_i},{x_j},{y_j},{z_j},{dist}",
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Multiplies the x-coordinates of the last pair the minimum spanning tree `edges` connects
+fn part2(boxes: &[JunctionBox], edges: &[Edge]) -> Result<i64> {
+    let last = edges
+        .last()
+        .ok_or_else(|| anyhow!("at least two junction boxes are required"))?;
+
+    boxes[last.i].coords[0]
+        .checked_mul(boxes[last.j].coords[0])
+        .ok_or_else(|| anyhow!("product of x-coordinates overflows"))
 }
 
 fn main() -> Result<()> {
+    let args = Args::parse()?;
+
     let input = fs::read_to_string("in/day8.txt")?;
     let boxes = input
         .lines()
         .map(JunctionBox::from_str)
         .collect::<Result<Vec<_>>>()?;
 
-    let part1 = self::part1(&boxes);
-    let part2 = self::part2(&boxes);
+    let connections = args.connections.unwrap_or(CONNECTIONS);
+    let top = args.top.unwrap_or(TOP);
 
+    let part1 = self::part1(&boxes, args.metric, connections, top)?;
     println!("Part 1: {part1}");
+
+    let edges = self::minimum_spanning_tree(&boxes, args.metric)?;
+    let part2 = self::part2(&boxes, &edges)?;
     println!("Part 2: {part2}");
 
-    assert_eq!(part1, 129_564);
-    assert_eq!(part2, 42_047_840);
+    if args.dot.is_some() || args.csv.is_some() || args.linkage.is_some() || args.cut.is_some() {
+        if let Some(path) = &args.dot {
+            let mut w = BufWriter::new(File::create(path)?);
+            self::write_dot(&mut w, &boxes, &edges)?;
+            w.flush()?;
+        }
+
+        if let Some(path) = &args.csv {
+            let mut w = BufWriter::new(File::create(path)?);
+            self::write_csv(&mut w, &boxes, &edges)?;
+            w.flush()?;
+        }
+
+        let dendrogram = Dendrogram::new(&boxes, &edges);
+
+        if let Some(path) = &args.linkage {
+            let mut w = BufWriter::new(File::create(path)?);
+            self::write_linkage(&mut w, &dendrogram)?;
+            w.flush()?;
+        }
+
+        if let Some(cut) = args.cut {
+            for (idx, cluster) in dendrogram.cut(cut)?.iter().enumerate() {
+                let cluster = cluster
+                    .iter()
+                    .map(usize::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                println!("Cluster {idx}: {cluster}");
+            }
+        }
+    }
+
+    if matches!(args.metric, Metric::SquaredEuclidean) {
+        if (connections, top) == (CONNECTIONS, TOP) {
+            assert_eq!(part1, 129_564);
+        }
+
+        assert_eq!(part2, 42_047_840);
+    }
 
     Ok(())
 }
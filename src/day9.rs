@@ -1,14 +1,18 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
 use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use anyhow::anyhow;
 use anyhow::Error;
 use anyhow::Result;
-use geo::Contains;
-use geo::Coord;
-use geo::LineString;
-use geo::Polygon;
-use geo::Rect;
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct Point {
@@ -16,13 +20,24 @@ struct Point {
     y: i64,
 }
 
+/// Rectangle with red tiles in two opposite corners
 #[derive(Debug)]
 struct Rectangle {
-    points: [Point; 4],
+    corners: [Point; 2],
+    area: i64,
 }
 
 struct Theatre {
-    polygon: Polygon,
+    red_tiles: Vec<Point>,
+    xs: Vec<i64>,
+    ys: Vec<i64>,
+    prefix: Vec<u32>,
+}
+
+#[derive(Debug)]
+struct Args {
+    svg: Option<PathBuf>,
+    top: usize,
 }
 
 impl FromStr for Point {
@@ -45,139 +60,418 @@ impl FromStr for Point {
 }
 
 impl Point {
-    const fn try_rectangle(&self, other: &Self) -> Option<Rectangle> {
+    fn try_rectangle(&self, other: &Self) -> Option<Rectangle> {
         if self.x == other.x || self.y == other.y {
             return None;
         }
 
-        let p1 = Self {
-            x: self.x,
-            y: self.y,
-        };
-        let p2 = Self {
-            x: self.x,
-            y: other.y,
-        };
-        let p3 = Self {
-            x: other.x,
-            y: other.y,
-        };
-        let p4 = Self {
-            x: other.x,
-            y: self.y,
-        };
-
         Some(Rectangle {
-            points: [p1, p2, p3, p4],
+            corners: [self.clone(), other.clone()],
+            area: ((self.x - other.x).abs() + 1) * ((self.y - other.y).abs() + 1),
         })
     }
+}
 
-    #[allow(clippy::cast_precision_loss)]
-    const fn to_geo_coord(&self) -> Coord<f64> {
-        Coord {
-            x: self.x as f64,
-            y: self.y as f64,
-        }
+impl Display for Rectangle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let [p, q] = &self.corners;
+        write!(f, "{},{} to {},{} (area {})", p.x, p.y, q.x, q.y, self.area)
     }
 }
 
 impl Rectangle {
     fn x_min(&self) -> i64 {
-        self.points.iter().map(|p| p.x).min().unwrap_or_default()
+        self.corners.iter().map(|p| p.x).min().unwrap_or_default()
     }
 
     fn x_max(&self) -> i64 {
-        self.points.iter().map(|p| p.x).max().unwrap_or_default()
+        self.corners.iter().map(|p| p.x).max().unwrap_or_default()
     }
 
     fn y_min(&self) -> i64 {
-        self.points.iter().map(|p| p.y).min().unwrap_or_default()
+        self.corners.iter().map(|p| p.y).min().unwrap_or_default()
     }
 
     fn y_max(&self) -> i64 {
-        self.points.iter().map(|p| p.y).max().unwrap_or_default()
+        self.corners.iter().map(|p| p.y).max().unwrap_or_default()
     }
+}
 
-    fn area(&self) -> i64 {
-        (self.x_max() - self.x_min() + 1) * (self.y_max() - self.y_min() + 1)
-    }
+impl Theatre {
+    /// Compresses the plane to the rows and columns of red tiles plus the gaps between them,
+    /// padded by a gap on each side, so flooding from a corner reaches everything outside the loop.
+    /// A gap between neighboring tiles holds no tiles, so the flood may pass through it but it is
+    /// never counted as outside
+    fn new(red_tiles: &[Point]) -> Result<Self> {
+        Self::validate(red_tiles)?;
+
+        let mut xs = red_tiles.iter().map(|p| p.x).collect::<Vec<_>>();
+        xs.sort_unstable();
+        xs.dedup();
+
+        let mut ys = red_tiles.iter().map(|p| p.y).collect::<Vec<_>>();
+        ys.sort_unstable();
+        ys.dedup();
+
+        let (row_len, col_len) = (2 * ys.len() + 1, 2 * xs.len() + 1);
+        if u32::try_from(row_len * col_len).is_err() {
+            return Err(anyhow!(
+                "{row_len}x{col_len} compressed grid has too many cells to count in u32"
+            ));
+        }
+
+        // Cells are stored row by row in flat grids
+        let mut boundary = vec![false; row_len * col_len];
+
+        for (p, q) in red_tiles.iter().zip(red_tiles.iter().cycle().skip(1)) {
+            let (row_p, col_p) = Self::compress(&xs, &ys, p);
+            let (row_q, col_q) = Self::compress(&xs, &ys, q);
+
+            for row in row_p.min(row_q)..=row_p.max(row_q) {
+                boundary[row * col_len + col_p.min(col_q)..=row * col_len + col_p.max(col_q)]
+                    .fill(true);
+            }
+        }
+
+        let mut outside = vec![false; row_len * col_len];
+        let mut stack = vec![(0_usize, 0_usize)];
+        outside[0] = true;
+
+        while let Some((row, col)) = stack.pop() {
+            let neighbors = [
+                (row.checked_sub(1), Some(col)),
+                (row.checked_add(1), Some(col)),
+                (Some(row), col.checked_sub(1)),
+                (Some(row), col.checked_add(1)),
+            ];
+
+            for (row_next, col_next) in neighbors {
+                let (Some(row_next), Some(col_next)) = (row_next, col_next) else {
+                    continue;
+                };
+
+                let next = row_next * col_len + col_next;
 
-    #[allow(clippy::cast_precision_loss)]
-    fn to_geo_rect(&self) -> Rect<f64> {
-        Rect::new(
-            Coord {
-                x: self.x_min() as f64 + 0.5,
-                y: self.y_min() as f64 + 0.5,
-            },
-            Coord {
-                x: self.x_max() as f64 - 0.5,
-                y: self.y_max() as f64 - 0.5,
-            },
-        )
+                if row_next < row_len && col_next < col_len && !boundary[next] && !outside[next] {
+                    outside[next] = true;
+                    stack.push((row_next, col_next));
+                }
+            }
+        }
+
+        drop(boundary);
+
+        // Number of outside cells above and to the left of each cell, with an extra leading row
+        // and column of zeros
+        let stride = col_len + 1;
+        let mut prefix = vec![0; (row_len + 1) * stride];
+        for row in 0..row_len {
+            for col in 0..col_len {
+                prefix[(row + 1) * stride + col + 1] = prefix[row * stride + col + 1]
+                    - prefix[row * stride + col]
+                    + prefix[(row + 1) * stride + col]
+                    + u32::from(
+                        outside[row * col_len + col]
+                            && Self::has_tiles(&ys, row)
+                            && Self::has_tiles(&xs, col),
+                    );
+            }
+        }
+
+        drop(outside);
+
+        Ok(Self {
+            red_tiles: red_tiles.to_vec(),
+            xs,
+            ys,
+            prefix,
+        })
     }
-}
 
-impl Theatre {
-    fn new(red_tiles: &[Point]) -> Self {
-        let coords = red_tiles.iter().map(Point::to_geo_coord).collect();
+    /// Checks that the red tiles form a closed, simple loop of axis-aligned edges, reporting each
+    /// problem by input line number
+    fn validate(red_tiles: &[Point]) -> Result<()> {
+        if red_tiles.len() < 4 {
+            return Err(anyhow!(
+                "red tile loop needs at least 4 tiles, found {}",
+                red_tiles.len(),
+            ));
+        }
+
+        let mut errors = Vec::new();
+
+        let mut lines = HashMap::with_capacity(red_tiles.len());
+        for (line, tile) in (1..).zip(red_tiles) {
+            if let Some(line_first) = lines.insert(tile, line) {
+                errors.push(format!(
+                    "line {line}: duplicates red tile on line {line_first}"
+                ));
+            }
+        }
 
-        Self {
-            polygon: Polygon::new(LineString::new(coords), vec![]),
+        // Edge `i` joins the tiles on lines `i + 1` and `i + 2`, wrapping back to line 1
+        let edges = red_tiles
+            .iter()
+            .zip(red_tiles.iter().cycle().skip(1))
+            .collect::<Vec<_>>();
+        let line = |i: usize| i % red_tiles.len() + 1;
+
+        for (i, (p, q)) in edges.iter().enumerate() {
+            if p.x != q.x && p.y != q.y {
+                errors.push(format!(
+                    "lines {} and {}: red tiles share neither a row nor a column",
+                    line(i),
+                    line(i + 1),
+                ));
+            }
         }
+
+        for i in 0..edges.len() {
+            for j in i + 1..edges.len() {
+                let ((p1, q1), (p2, q2)) = (edges[i], edges[j]);
+
+                if (p1.x != q1.x && p1.y != q1.y) || (p2.x != q2.x && p2.y != q2.y) {
+                    continue;
+                }
+
+                let x_min = p1.x.min(q1.x).max(p2.x.min(q2.x));
+                let x_max = p1.x.max(q1.x).min(p2.x.max(q2.x));
+                let y_min = p1.y.min(q1.y).max(p2.y.min(q2.y));
+                let y_max = p1.y.max(q1.y).min(p2.y.max(q2.y));
+
+                if x_min > x_max || y_min > y_max {
+                    continue;
+                }
+
+                // Neighboring edges may only meet at their shared tile
+                let shared = if j == i + 1 {
+                    Some(q1)
+                } else if i == 0 && j == edges.len() - 1 {
+                    Some(p1)
+                } else {
+                    None
+                };
+
+                if shared.is_some_and(|t| (x_min, x_max, y_min, y_max) == (t.x, t.x, t.y, t.y)) {
+                    continue;
+                }
+
+                errors.push(format!(
+                    "edge from line {} to {} intersects edge from line {} to {}",
+                    line(i),
+                    line(i + 1),
+                    line(j),
+                    line(j + 1),
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("invalid red tile loop:\n{}", errors.join("\n")))
+        }
+    }
+
+    /// Row and column of a red tile in the compressed grid
+    fn compress(xs: &[i64], ys: &[i64], point: &Point) -> (usize, usize) {
+        let row = ys.partition_point(|&y| y < point.y);
+        let col = xs.partition_point(|&x| x < point.x);
+
+        (2 * row + 1, 2 * col + 1)
     }
 
+    /// Whether compressed row or column `idx` over `coords` holds any tiles, which only a gap
+    /// between neighboring coordinates does not
+    fn has_tiles(coords: &[i64], idx: usize) -> bool {
+        idx % 2 == 1
+            || idx == 0
+            || idx == 2 * coords.len()
+            || coords[idx / 2] - coords[idx / 2 - 1] > 1
+    }
+
+    /// Whether every tile of `rect`, whose corners must be red tiles, is red or green
     fn contains_rect(&self, rect: &Rectangle) -> bool {
-        let rect = rect.to_geo_rect();
-        self.polygon.contains(&rect)
+        let min = Point {
+            x: rect.x_min(),
+            y: rect.y_min(),
+        };
+        let max = Point {
+            x: rect.x_max(),
+            y: rect.y_max(),
+        };
+
+        let (row_min, col_min) = Self::compress(&self.xs, &self.ys, &min);
+        let (row_max, col_max) = Self::compress(&self.xs, &self.ys, &max);
+
+        let stride = 2 * self.xs.len() + 2;
+        let outside = (self.prefix[(row_max + 1) * stride + col_max + 1]
+            - self.prefix[row_min * stride + col_max + 1])
+            - (self.prefix[(row_max + 1) * stride + col_min]
+                - self.prefix[row_min * stride + col_min]);
+
+        outside == 0
     }
 }
 
-fn part1(points: &[Point]) -> i64 {
-    let mut area_max = 0;
-
-    for i in 0..points.len() - 1 {
-        for j in i + 1..points.len() {
-            if let Some(rect) = points[i].try_rectangle(&points[j]) {
-                area_max = area_max.max(rect.area());
+impl Args {
+    fn parse() -> Result<Self> {
+        let mut args = env::args().skip(1);
+        let mut parsed = Self { svg: None, top: 1 };
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| anyhow!("missing value for '{arg}'"))
+            };
+
+            match arg.as_str() {
+                "--svg" => parsed.svg = Some(PathBuf::from(value()?)),
+                "--top" => parsed.top = value()?.parse()?,
+                _ => return Err(anyhow!("unknown argument '{arg}'")),
             }
         }
-    }
 
-    area_max
+        Ok(parsed)
+    }
 }
 
-fn part2(points: &[Point]) -> i64 {
-    let mut area_max = 0;
+/// Draws the loop of red tiles, green inside, with each of `rects` outlined in its colour
+#[allow(clippy::cast_precision_loss)]
+fn write_svg(w: &mut impl Write, theatre: &Theatre, rects: &[(&Rectangle, &str)]) -> Result<()> {
+    const SIZE: f64 = 1_000.0;
+    const MARGIN: f64 = 10.0;
+
+    let x_min = theatre.xs.first().copied().unwrap_or_default();
+    let x_max = theatre.xs.last().copied().unwrap_or_default();
+    let y_min = theatre.ys.first().copied().unwrap_or_default();
+    let y_max = theatre.ys.last().copied().unwrap_or_default();
+
+    let scale = SIZE / x_max.abs_diff(x_min).max(y_max.abs_diff(y_min)).max(1) as f64;
+    let width = (x_max.abs_diff(x_min) as f64).mul_add(scale, 2.0 * MARGIN);
+    let height = (y_max.abs_diff(y_min) as f64).mul_add(scale, 2.0 * MARGIN);
+
+    let x = |x: i64| (x.abs_diff(x_min) as f64).mul_add(scale, MARGIN);
+    let y = |y: i64| (y.abs_diff(y_min) as f64).mul_add(scale, MARGIN);
+
+    writeln!(
+        w,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width:.0}\" height=\"{height:.0}\">",
+    )?;
+
+    let points = theatre
+        .red_tiles
+        .iter()
+        .map(|p| format!("{:.2},{:.2}", x(p.x), y(p.y)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    writeln!(
+        w,
+        "  <polygon points=\"{points}\" fill=\"green\" fill-opacity=\"0.3\" stroke=\"green\"/>",
+    )?;
+
+    for p in &theatre.red_tiles {
+        writeln!(
+            w,
+            "  <circle cx=\"{:.2}\" cy=\"{:.2}\" r=\"2\" fill=\"red\"/>",
+            x(p.x),
+            y(p.y),
+        )?;
+    }
+
+    for (rect, colour) in rects {
+        writeln!(
+            w,
+            "  <rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"none\" \
+             stroke=\"{colour}\" stroke-width=\"2\"/>",
+            x(rect.x_min()),
+            y(rect.y_min()),
+            rect.x_max().abs_diff(rect.x_min()) as f64 * scale,
+            rect.y_max().abs_diff(rect.y_min()) as f64 * scale,
+        )?;
+    }
 
-    let theatre = Theatre::new(points);
+    writeln!(w, "</svg>")?;
 
-    for i in 0..points.len() - 1 {
+    Ok(())
+}
+
+/// The `k` largest rectangles between pairs of `points` accepted by `is_valid`, largest first
+fn largest(points: &[Point], k: usize, is_valid: impl Fn(&Rectangle) -> bool) -> Vec<Rectangle> {
+    let mut largest = BinaryHeap::with_capacity(k + 1);
+
+    for i in 0..points.len() {
         for j in i + 1..points.len() {
             if let Some(rect) = points[i].try_rectangle(&points[j]) {
-                let area = rect.area();
+                let is_larger = largest.len() < k
+                    || largest
+                        .peek()
+                        .is_some_and(|&Reverse((area, _, _))| rect.area > area);
+
+                if is_larger && is_valid(&rect) {
+                    largest.push(Reverse((rect.area, i, j)));
 
-                if area > area_max && theatre.contains_rect(&rect) {
-                    area_max = area;
+                    if largest.len() > k {
+                        largest.pop();
+                    }
                 }
             }
         }
     }
 
-    area_max
+    largest
+        .into_sorted_vec()
+        .into_iter()
+        .filter_map(|Reverse((_, i, j))| points[i].try_rectangle(&points[j]))
+        .collect()
+}
+
+fn part1(points: &[Point], k: usize) -> Vec<Rectangle> {
+    self::largest(points, k, |_| true)
+}
+
+fn part2(theatre: &Theatre, k: usize) -> Vec<Rectangle> {
+    self::largest(&theatre.red_tiles, k, |rect| theatre.contains_rect(rect))
 }
 
 fn main() -> Result<()> {
+    let args = Args::parse()?;
+
     let input = fs::read_to_string("in/day9.txt")?;
     let points = input
         .lines()
         .map(Point::from_str)
         .collect::<Result<Vec<_>>>()?;
 
-    let part1 = self::part1(&points);
-    let part2 = self::part2(&points);
+    let theatre = Theatre::new(&points)?;
+
+    let largest1 = self::part1(&points, args.top);
+    let largest2 = self::part2(&theatre, args.top);
+
+    let part1 = largest1.first().map_or(0, |rect| rect.area);
+    let part2 = largest2.first().map_or(0, |rect| rect.area);
 
     println!("Part 1: {part1}");
+    for (rank, rect) in (1..).zip(&largest1) {
+        println!("  #{rank}: {rect}");
+    }
+
     println!("Part 2: {part2}");
+    for (rank, rect) in (1..).zip(&largest2) {
+        println!("  #{rank}: {rect}");
+    }
+
+    if let Some(path) = &args.svg {
+        let rects = [(largest1.first(), "blue"), (largest2.first(), "orange")];
+        let rects = rects
+            .into_iter()
+            .filter_map(|(rect, colour)| rect.map(|rect| (rect, colour)))
+            .collect::<Vec<_>>();
+
+        let mut w = BufWriter::new(File::create(path)?);
+        self::write_svg(&mut w, &theatre, &rects)?;
+        w.flush()?;
+    }
 
     assert_eq!(part1, 4_749_838_800);
     assert_eq!(part2, 1_624_057_680);